@@ -1,16 +1,49 @@
 use anyhow::{Result, ensure};
 use bitvec_helpers::bitstream_io_writer::BitstreamIoWriter;
-use hevc::{NAL_SEI_PREFIX, USER_DATA_REGISTERED_ITU_T_35};
-use hevc_parser::{hevc, utils::add_start_code_emulation_prevention_3_byte};
+use hevc::{NAL_SEI_PREFIX, SeiMessage, USER_DATA_REGISTERED_ITU_T_35};
+use hevc_parser::{
+    hevc,
+    utils::{
+        add_start_code_emulation_prevention_3_byte, clear_start_code_emulation_prevention_3_byte,
+    },
+};
 
 use super::metadata::{Hdr10PlusMetadata, Hdr10PlusMetadataEncOpts};
 use super::metadata_json::Hdr10PlusJsonMetadata;
 
+/// NAL unit header layer and temporal sub-layer identifiers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NalHeaderIds {
+    pub nuh_layer_id: u8,
+    /// TemporalId, `nuh_temporal_id_plus1 - 1`
+    pub temporal_id: u8,
+}
+
 pub fn encode_hdr10plus_nal(metadata: &Hdr10PlusMetadata, validate: bool) -> Result<Vec<u8>> {
-    let opts = Hdr10PlusMetadataEncOpts {
-        validate,
-        ..Default::default()
-    };
+    encode_hdr10plus_nal_with_ids(metadata, validate, None)
+}
+
+/// Same as `encode_hdr10plus_nal`, with the NAL header identifiers to use.
+/// The SEI's TemporalId must be the same as the access unit it is inserted in.
+///
+/// Defaults to layer 0 and TemporalId 0 when `None`.
+pub fn encode_hdr10plus_nal_with_ids(
+    metadata: &Hdr10PlusMetadata,
+    validate: bool,
+    ids: Option<NalHeaderIds>,
+) -> Result<Vec<u8>> {
+    let ids = ids.unwrap_or_default();
+
+    ensure!(
+        ids.nuh_layer_id < 63,
+        "Invalid nuh_layer_id: {}",
+        ids.nuh_layer_id
+    );
+    ensure!(
+        ids.temporal_id < 7,
+        "Invalid TemporalId: {}",
+        ids.temporal_id
+    );
 
     // Write NALU SEI_PREFIX header
     let mut header_writer = BitstreamIoWriter::with_capacity(64);
@@ -18,33 +51,95 @@ pub fn encode_hdr10plus_nal(metadata: &Hdr10PlusMetadata, validate: bool) -> Res
     header_writer.write_bit(false)?; // forbidden_zero_bit
 
     header_writer.write::<6, u8>(NAL_SEI_PREFIX)?; // nal_type
-    header_writer.write_const::<6, 0>()?; // nuh_layer_id
-    header_writer.write_const::<3, 1>()?; // nuh_temporal_id_plus1
+    header_writer.write::<6, u8>(ids.nuh_layer_id)?; // nuh_layer_id
+    header_writer.write::<3, u8>(ids.temporal_id + 1)?; // nuh_temporal_id_plus1
 
-    header_writer.write::<8, u8>(USER_DATA_REGISTERED_ITU_T_35)?;
+    let mut data = header_writer.into_inner();
+    data.append(&mut encode_hdr10plus_sei_message(metadata, validate)?);
 
-    let mut payload = metadata.encode_with_opts(&opts)?;
+    // rbsp_trailing_bits
+    data.push(0x80);
 
-    // FIXME: This should probably be 1024 but not sure how to write a longer header
-    ensure!(
-        payload.len() <= 255,
-        "Payload too large: {} bytes",
-        payload.len()
-    );
+    add_start_code_emulation_prevention_3_byte(&mut data);
 
-    header_writer.write::<8, u8>(payload.len() as u8)?;
+    Ok(data)
+}
 
-    payload.push(0x80);
+pub fn encode_hevc_from_json(metadata: &Hdr10PlusJsonMetadata, validate: bool) -> Result<Vec<u8>> {
+    let meta = Hdr10PlusMetadata::try_from(metadata)?;
+    encode_hdr10plus_nal(&meta, validate)
+}
+
+/// Encodes the metadata as a `user_data_registered_itu_t_t35` SEI message,
+/// including the payload type and size.
+///
+/// The bytes do not contain any `emulation_prevention_three_byte`.
+pub fn encode_hdr10plus_sei_message(
+    metadata: &Hdr10PlusMetadata,
+    validate: bool,
+) -> Result<Vec<u8>> {
+    let opts = Hdr10PlusMetadataEncOpts {
+        validate,
+        ..Default::default()
+    };
+
+    let mut payload = metadata.encode_with_opts(&opts)?;
+
+    let mut data = Vec::with_capacity(payload.len() + 3);
+    data.push(USER_DATA_REGISTERED_ITU_T_35);
+
+    let mut payload_size = payload.len();
+    while payload_size >= 0xFF {
+        data.push(0xFF);
+        payload_size -= 0xFF;
+    }
+    data.push(payload_size as u8);
 
-    let mut data = header_writer.into_inner();
     data.append(&mut payload);
 
+    Ok(data)
+}
+
+/// Appends the metadata as a new SEI message at the end of an existing SEI NAL unit.
+///
+/// `nal_data` is the NAL unit without start code, and the returned data
+/// has the `emulation_prevention_three_byte`s added back.
+pub fn append_hdr10plus_sei_message(
+    nal_data: &[u8],
+    metadata: &Hdr10PlusMetadata,
+    validate: bool,
+) -> Result<Vec<u8>> {
+    let mut data = clear_start_code_emulation_prevention_3_byte(nal_data);
+    let messages = SeiMessage::parse_sei_rbsp(&data)?;
+
+    let last_msg = messages.last();
+    ensure!(last_msg.is_some(), "SEI NAL unit has no SEI message");
+
+    // Drop the rbsp_trailing_bits, the new message goes right after the last one
+    let end = last_msg
+        .map(|msg| msg.payload_offset + msg.payload_size)
+        .unwrap_or_default();
+    data.truncate(end);
+
+    data.append(&mut encode_hdr10plus_sei_message(metadata, validate)?);
+
+    // rbsp_trailing_bits
+    data.push(0x80);
+
     add_start_code_emulation_prevention_3_byte(&mut data);
 
     Ok(data)
 }
 
-pub fn encode_hevc_from_json(metadata: &Hdr10PlusJsonMetadata, validate: bool) -> Result<Vec<u8>> {
-    let meta = Hdr10PlusMetadata::try_from(metadata)?;
-    encode_hdr10plus_nal(&meta, validate)
+impl NalHeaderIds {
+    /// Reads the identifiers from the 2 bytes NAL unit header
+    pub fn from_nal_header(data: &[u8]) -> Option<Self> {
+        match data {
+            [first, second, ..] => Some(Self {
+                nuh_layer_id: ((first & 0x01) << 5) | (second >> 3),
+                temporal_id: (second & 0x07).saturating_sub(1),
+            }),
+            _ => None,
+        }
+    }
 }
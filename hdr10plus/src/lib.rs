@@ -1,4 +1,5 @@
 pub mod metadata;
+pub mod metadata_binary;
 
 #[cfg(feature = "json")]
 pub mod metadata_json;
@@ -249,7 +249,8 @@ impl Hdr10PlusMetadata {
         Ok(())
     }
 
-    pub(crate) fn set_profile(&mut self) {
+    /// Sets `profile` from the tone mapping flag, target display luminance and Bezier curve
+    pub fn set_profile(&mut self) {
         let profile = if self.tone_mapping_flag
             && self.targeted_system_display_maximum_luminance > 0
         {
@@ -560,7 +561,7 @@ impl BezierCurve {
         Ok(bc)
     }
 
-    fn validate(&self) -> Result<()> {
+    pub fn validate(&self) -> Result<()> {
         // The value of knee_point_x shall be in the range of 0 to 1, and in multiples of 1/4095
         ensure!(
             self.knee_point_x <= 4095,
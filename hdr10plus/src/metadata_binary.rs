@@ -0,0 +1,279 @@
+//! Compact binary container for per-frame HDR10+ metadata.
+//!
+//! All integers are little endian.
+//!
+//! | Field | Type | Description |
+//! |---|---|---|
+//! | magic | 8 bytes | `HDR10+BM` |
+//! | version | `u16` | Format version, currently 1 |
+//! | frame_count | `u32` | Number of frames |
+//! | scene_count | `u32` | Number of entries in the scene index |
+//! | tool_name | `u8` length + UTF-8 | Tool that wrote the file |
+//! | tool_version | `u8` length + UTF-8 | Version of the tool |
+//! | frames | `frame_count` times | `u16` size, then the ITU-T T.35 payload |
+//! | scene index | `scene_count` times `u32` | Index of the first frame of each scene |
+//!
+//! The payloads are encoded with `Hdr10PlusMetadata::encode_with_opts`, including `itu_t_t35_country_code`.
+//! The frames are in presentation order.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail, ensure};
+
+use super::metadata::{Hdr10PlusMetadata, Hdr10PlusMetadataEncOpts};
+
+pub const MAGIC: &[u8; 8] = b"HDR10+BM";
+pub const VERSION: u16 = 1;
+
+/// Offset of `frame_count` from the start of the header
+const COUNTS_OFFSET: u64 = 10;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MetadataBinaryHeader {
+    pub version: u16,
+    pub frame_count: u32,
+    pub scene_count: u32,
+    pub tool_name: String,
+    pub tool_version: String,
+}
+
+/// Writes the frames one at a time.
+/// The counts are written in the header when finishing, so the writer must be seekable.
+pub struct MetadataBinaryWriter<W: Write + Seek> {
+    writer: W,
+    header_start: u64,
+    enc_opts: Hdr10PlusMetadataEncOpts,
+
+    frame_count: u32,
+    scene_first_frames: Vec<u32>,
+}
+
+/// Reads the frames one at a time.
+/// The scene index comes after the frames, and is available once they were all read.
+pub struct MetadataBinaryReader<R: Read> {
+    reader: R,
+    header: MetadataBinaryHeader,
+
+    frames_read: u32,
+    scene_first_frames: Option<Vec<u32>>,
+}
+
+impl<W: Write + Seek> MetadataBinaryWriter<W> {
+    pub fn new(mut writer: W, tool_name: &str, tool_version: &str, validate: bool) -> Result<Self> {
+        let header_start = writer.stream_position()?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+
+        // frame_count and scene_count, written when finishing
+        writer.write_all(&[0; 8])?;
+
+        write_string(&mut writer, tool_name)?;
+        write_string(&mut writer, tool_version)?;
+
+        Ok(Self {
+            writer,
+            header_start,
+            enc_opts: Hdr10PlusMetadataEncOpts {
+                validate,
+                with_country_code: true,
+            },
+            frame_count: 0,
+            scene_first_frames: Vec::new(),
+        })
+    }
+
+    /// `scene_start` marks the frame as the first of a new scene, the first frame always is
+    pub fn write_frame(&mut self, metadata: &Hdr10PlusMetadata, scene_start: bool) -> Result<()> {
+        let payload = metadata.encode_with_opts(&self.enc_opts)?;
+        self.write_payload(&payload, scene_start)
+    }
+
+    /// Same as `write_frame`, with an already encoded T.35 payload
+    pub fn write_payload(&mut self, payload: &[u8], scene_start: bool) -> Result<()> {
+        let size = u16::try_from(payload.len()).context("HDR10+ payload too large")?;
+        ensure!(self.frame_count < u32::MAX, "Too many frames");
+
+        self.writer.write_all(&size.to_le_bytes())?;
+        self.writer.write_all(payload)?;
+
+        if scene_start || self.frame_count == 0 {
+            self.scene_first_frames.push(self.frame_count);
+        }
+
+        self.frame_count += 1;
+
+        Ok(())
+    }
+
+    /// Writes the scene index and the counts
+    pub fn finish(mut self) -> Result<W> {
+        for first_frame in &self.scene_first_frames {
+            self.writer.write_all(&first_frame.to_le_bytes())?;
+        }
+
+        let end = self.writer.stream_position()?;
+        let scene_count = self.scene_first_frames.len() as u32;
+
+        self.writer
+            .seek(SeekFrom::Start(self.header_start + COUNTS_OFFSET))?;
+        self.writer.write_all(&self.frame_count.to_le_bytes())?;
+        self.writer.write_all(&scene_count.to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(end))?;
+
+        Ok(self.writer)
+    }
+}
+
+impl MetadataBinaryReader<BufReader<File>> {
+    pub fn from_file<P: AsRef<Path>>(input: P) -> Result<Self> {
+        let file = File::open(input)?;
+        Self::new(BufReader::with_capacity(100_000, file))
+    }
+}
+
+impl<R: Read> MetadataBinaryReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0; 8];
+        reader
+            .read_exact(&mut magic)
+            .context("Failed reading binary metadata header")?;
+        ensure!(&magic == MAGIC, "Not a HDR10+ binary metadata file");
+
+        let version = read_u16(&mut reader)?;
+        ensure!(
+            version <= VERSION,
+            "Unsupported HDR10+ binary metadata version {version}"
+        );
+
+        let header = MetadataBinaryHeader {
+            version,
+            frame_count: read_u32(&mut reader)?,
+            scene_count: read_u32(&mut reader)?,
+            tool_name: read_string(&mut reader)?,
+            tool_version: read_string(&mut reader)?,
+        };
+
+        Ok(Self {
+            reader,
+            header,
+            frames_read: 0,
+            scene_first_frames: None,
+        })
+    }
+
+    pub fn header(&self) -> &MetadataBinaryHeader {
+        &self.header
+    }
+
+    /// Number of frames read so far
+    pub fn frame_count(&self) -> usize {
+        self.frames_read as usize
+    }
+
+    /// Only available once all the frames were read
+    pub fn scene_first_frames(&self) -> Option<&[u32]> {
+        self.scene_first_frames.as_deref()
+    }
+
+    /// Reads the next T.35 payload, `None` once all the frames were read
+    pub fn next_payload(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.frames_read == self.header.frame_count {
+            if self.scene_first_frames.is_none() {
+                self.read_scene_index()?;
+            }
+
+            return Ok(None);
+        }
+
+        let frame = self.frames_read;
+        let size = read_u16(&mut self.reader)
+            .with_context(|| format!("Failed reading payload size of frame {frame}"))?;
+
+        let mut payload = vec![0; size as usize];
+        self.reader
+            .read_exact(&mut payload)
+            .with_context(|| format!("Failed reading payload of frame {frame}"))?;
+
+        self.frames_read += 1;
+
+        Ok(Some(payload))
+    }
+
+    pub fn next_frame(&mut self) -> Result<Option<Hdr10PlusMetadata>> {
+        let frame = self.frames_read;
+
+        self.next_payload()?
+            .map(|payload| {
+                Hdr10PlusMetadata::parse(&payload)
+                    .with_context(|| format!("Failed parsing HDR10+ metadata of frame {frame}"))
+            })
+            .transpose()
+    }
+
+    fn read_scene_index(&mut self) -> Result<()> {
+        let scene_first_frames = (0..self.header.scene_count)
+            .map(|_| read_u32(&mut self.reader))
+            .collect::<Result<Vec<_>>>()
+            .context("Failed reading scene index")?;
+
+        self.scene_first_frames = Some(scene_first_frames);
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for MetadataBinaryReader<R> {
+    type Item = Result<Hdr10PlusMetadata>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+/// Checks the file starts with the binary metadata magic
+pub fn is_metadata_binary<P: AsRef<Path>>(input: P) -> Result<bool> {
+    let mut magic = Vec::with_capacity(MAGIC.len());
+    File::open(input)?
+        .take(MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+
+    Ok(magic == MAGIC)
+}
+
+fn write_string<W: Write>(writer: &mut W, value: &str) -> Result<()> {
+    let Ok(len) = u8::try_from(value.len()) else {
+        bail!("String too long for binary metadata header: {value}");
+    };
+
+    writer.write_all(&[len])?;
+    writer.write_all(value.as_bytes())?;
+
+    Ok(())
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String> {
+    let mut len = [0; 1];
+    reader.read_exact(&mut len)?;
+
+    let mut bytes = vec![0; len[0] as usize];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
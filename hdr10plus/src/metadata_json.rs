@@ -1,6 +1,12 @@
-use std::{convert::TryFrom, fs::File, io::Read, path::Path};
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+};
 
-use anyhow::{Result, bail, ensure};
+use anyhow::{Context, Result, bail, ensure};
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 
@@ -45,6 +51,73 @@ pub struct Hdr10PlusJsonMetadata {
     pub sequence_frame_index: usize,
 }
 
+/// Scene position of a frame in the metadata list
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SceneFrameInfo {
+    pub scene_frame_index: usize,
+    pub scene_id: usize,
+    pub sequence_frame_index: usize,
+}
+
+/// Reads a metadata JSON incrementally, yielding the `SceneInfo` frames one at a time.
+///
+/// The other root values are read as they are encountered:
+/// `JSONInfo` is usually available right away, and `SceneInfoSummary` once all the frames were read.
+pub struct MetadataJsonReader<R: BufRead> {
+    reader: R,
+    state: ReaderState,
+    frame_count: usize,
+    has_scene_info: bool,
+
+    info: Option<JsonInfo>,
+    scene_info_summary: Option<SceneInfoSummary>,
+    tool_info: Option<ToolInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReaderState {
+    /// Inside the root object
+    Root {
+        first: bool,
+    },
+    /// Inside the `SceneInfo` array
+    Frames {
+        first: bool,
+    },
+    Done,
+}
+
+/// Writes the metadata JSON one frame at a time, in presentation order.
+///
+/// The output is the same as `generate_json` serialized with `serde_json::to_string_pretty`,
+/// but only the previous frame is kept to compute the scene information.
+///
+/// `JSONInfo` is written first, so the profile of the whole list must be known beforehand.
+pub struct MetadataJsonWriter<W: Write> {
+    writer: W,
+    profile: String,
+    scenes: SceneTracker,
+    previous: Option<Hdr10PlusMetadata>,
+    first_frames: Vec<usize>,
+}
+
+/// A `SceneInfo` frame serialized before its scene information is known.
+///
+/// The scene fields are inserted at `scene_offset` by `MetadataJsonWriter::write_serialized_frame`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerializedFrame {
+    pub text: String,
+    pub scene_offset: usize,
+}
+
+/// Computes the scene information of the frames one at a time, same as `compute_scene_frames`
+#[derive(Debug, Default)]
+pub struct SceneTracker {
+    scene_frame_index: usize,
+    scene_id: usize,
+    frame_count: usize,
+}
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct SceneInfoSummary {
@@ -103,106 +176,339 @@ impl MetadataJsonRoot {
     }
 }
 
+impl MetadataJsonReader<BufReader<File>> {
+    pub fn from_file<P: AsRef<Path>>(input: P) -> Result<Self> {
+        let file = File::open(input)?;
+        Self::new(BufReader::with_capacity(100_000, file))
+    }
+}
+
+impl<R: BufRead> MetadataJsonReader<R> {
+    /// Reads the root object up to the first `SceneInfo` frame
+    pub fn new(reader: R) -> Result<Self> {
+        let mut json_reader = Self {
+            reader,
+            state: ReaderState::Root { first: true },
+            frame_count: 0,
+            has_scene_info: false,
+            info: None,
+            scene_info_summary: None,
+            tool_info: None,
+        };
+
+        json_reader
+            .expect_byte(b'{')
+            .context("Failed parsing JSON metadata")?;
+
+        while let ReaderState::Root { first } = json_reader.state {
+            json_reader
+                .read_root_entry(first)
+                .context("Failed parsing JSON metadata")?;
+        }
+
+        Ok(json_reader)
+    }
+
+    pub fn info(&self) -> Option<&JsonInfo> {
+        self.info.as_ref()
+    }
+
+    /// Only available once all the frames were read, when the summary comes last
+    pub fn scene_info_summary(&self) -> Option<&SceneInfoSummary> {
+        self.scene_info_summary.as_ref()
+    }
+
+    pub fn tool_info(&self) -> Option<&ToolInfo> {
+        self.tool_info.as_ref()
+    }
+
+    /// Number of frames read so far
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// Reads the next `SceneInfo` frame, `None` once the whole JSON was read
+    pub fn next_frame(&mut self) -> Result<Option<Hdr10PlusJsonMetadata>> {
+        let res = self.read_next_frame();
+
+        if res.is_err() {
+            self.state = ReaderState::Done;
+        }
+
+        res
+    }
+
+    fn read_next_frame(&mut self) -> Result<Option<Hdr10PlusJsonMetadata>> {
+        loop {
+            match self.state {
+                ReaderState::Root { first } => self
+                    .read_root_entry(first)
+                    .context("Failed parsing JSON metadata")?,
+                ReaderState::Frames { first } => {
+                    if self.peek_byte()? == b']' {
+                        self.reader.consume(1);
+                        self.state = ReaderState::Root { first: false };
+
+                        continue;
+                    }
+
+                    if !first {
+                        self.expect_byte(b',')?;
+                    }
+
+                    let frame = self.deserialize().with_context(|| {
+                        format!("Failed parsing SceneInfo frame {}", self.frame_count)
+                    })?;
+
+                    self.frame_count += 1;
+                    self.state = ReaderState::Frames { first: false };
+
+                    return Ok(Some(frame));
+                }
+                ReaderState::Done => return Ok(None),
+            }
+        }
+    }
+
+    /// Reads the next entry of the root object, stopping at the start of the `SceneInfo` array
+    fn read_root_entry(&mut self, first: bool) -> Result<()> {
+        if self.peek_byte()? == b'}' {
+            self.reader.consume(1);
+            self.state = ReaderState::Done;
+
+            ensure!(self.has_scene_info, "Missing SceneInfo");
+
+            return Ok(());
+        }
+
+        if !first {
+            self.expect_byte(b',')?;
+        }
+
+        let key: String = self.deserialize()?;
+        self.expect_byte(b':')?;
+
+        self.state = ReaderState::Root { first: false };
+
+        match key.as_str() {
+            "JSONInfo" => self.info = Some(self.deserialize().context("Invalid JSONInfo")?),
+            "SceneInfoSummary" => {
+                let summary = self.deserialize().context("Invalid SceneInfoSummary")?;
+                self.scene_info_summary = Some(summary);
+            }
+            "ToolInfo" => self.tool_info = Some(self.deserialize().context("Invalid ToolInfo")?),
+            "SceneInfo" => {
+                self.expect_byte(b'[')?;
+
+                self.has_scene_info = true;
+                self.state = ReaderState::Frames { first: true };
+            }
+            _ => self.skip_value()?,
+        }
+
+        Ok(())
+    }
+
+    /// Skips the whitespace, and returns the next byte without consuming it
+    fn peek_byte(&mut self) -> Result<u8> {
+        loop {
+            match self.reader.fill_buf()?.first() {
+                Some(byte) if byte.is_ascii_whitespace() => self.reader.consume(1),
+                Some(byte) => return Ok(*byte),
+                None => bail!("Unexpected end of JSON"),
+            }
+        }
+    }
+
+    fn expect_byte(&mut self, expected: u8) -> Result<()> {
+        let byte = self.peek_byte()?;
+        ensure!(
+            byte == expected,
+            "Expected '{}', found '{}'",
+            expected as char,
+            byte as char
+        );
+
+        self.reader.consume(1);
+
+        Ok(())
+    }
+
+    /// Objects, arrays, strings and literals end on their last byte,
+    /// so the deserializer never reads past the value.
+    fn deserialize<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let mut de = serde_json::Deserializer::from_reader(&mut self.reader);
+        Ok(T::deserialize(&mut de)?)
+    }
+
+    fn skip_value(&mut self) -> Result<()> {
+        let byte = self.peek_byte()?;
+
+        // The deserializer would need to read past the end of a number
+        if byte == b'-' || byte.is_ascii_digit() {
+            loop {
+                let buf = self.reader.fill_buf()?;
+                let len = buf
+                    .iter()
+                    .take_while(|b| {
+                        b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E')
+                    })
+                    .count();
+                let end_of_number = len < buf.len() || buf.is_empty();
+
+                self.reader.consume(len);
+
+                if end_of_number {
+                    break;
+                }
+            }
+        } else {
+            self.deserialize::<IgnoredAny>()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for MetadataJsonReader<R> {
+    type Item = Result<Hdr10PlusJsonMetadata>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
 pub fn generate_json(
     metadata: &[&Hdr10PlusMetadata],
     tool_name: &str,
     tool_version: &str,
 ) -> Value {
-    let (profile, frame_json_list): (String, Vec<Value>) = json_list(metadata);
+    let profile = metadata_list_profile(metadata);
+    let scene_info = compute_scene_frames(profile, metadata);
+    let frame_json_list = frame_json_list(profile, metadata, &scene_info);
 
     let version = metadata
         .first()
         .map(|meta| meta.application_version)
         .unwrap_or(1);
-    let json_info = json!({
-        "HDR10plusProfile": profile,
-        "Version": format!("{}.0", version),
-    });
+    let json_info = json_info(profile, version);
 
-    let first_frames: Vec<u64> = frame_json_list
+    let first_frames: Vec<usize> = scene_info
         .iter()
-        .filter_map(|meta| {
-            if meta.get("SceneFrameIndex").unwrap().as_u64().unwrap() == 0 {
-                meta.get("SequenceFrameIndex").unwrap().as_u64()
-            } else {
-                None
-            }
-        })
+        .filter(|frame| frame.scene_frame_index == 0)
+        .map(|frame| frame.sequence_frame_index)
         .collect();
+    let scene_info_json = scene_info_summary_json(first_frames, frame_json_list.len());
 
-    let mut scene_lengths: Vec<u64> = Vec::with_capacity(first_frames.len());
+    let final_json = json!({
+        "JSONInfo": json_info,
+        "SceneInfo": frame_json_list,
+        "SceneInfoSummary": scene_info_json,
+        "ToolInfo": tool_info_json(tool_name, tool_version),
+    });
+
+    final_json
+}
+
+fn json_info(profile: &str, version: u8) -> Value {
+    json!({
+        "HDR10plusProfile": profile,
+        "Version": format!("{}.0", version),
+    })
+}
+
+fn scene_info_summary_json(first_frames: Vec<usize>, frame_count: usize) -> Value {
+    let mut scene_lengths: Vec<usize> = Vec::with_capacity(first_frames.len());
 
     for i in 0..first_frames.len() {
         if i < first_frames.len() - 1 {
             scene_lengths.push(first_frames[i + 1] - first_frames[i]);
         } else {
-            scene_lengths.push(frame_json_list.len() as u64 - first_frames[i]);
+            scene_lengths.push(frame_count - first_frames[i]);
         }
     }
 
-    let scene_info_json = json!({
+    json!({
         "SceneFirstFrameIndex": first_frames,
         "SceneFrameNumbers": scene_lengths,
-    });
-
-    let final_json = json!({
-        "JSONInfo": json_info,
-        "SceneInfo": frame_json_list,
-        "SceneInfoSummary": scene_info_json,
-        "ToolInfo": json!({
-            "Tool": tool_name,
-            "Version": tool_version,
-        })
-    });
+    })
+}
 
-    final_json
+fn tool_info_json(tool_name: &str, tool_version: &str) -> Value {
+    json!({
+        "Tool": tool_name,
+        "Version": tool_version,
+    })
 }
 
 pub fn json_list(list: &[&Hdr10PlusMetadata]) -> (String, Vec<Value>) {
-    let profile = if list.iter().all(|m| m.profile == "B") {
+    let profile = metadata_list_profile(list);
+    let scene_info = compute_scene_frames(profile, list);
+
+    (
+        profile.to_string(),
+        frame_json_list(profile, list, &scene_info),
+    )
+}
+
+/// Profile of the whole list, `N/A` when the frames have different profiles
+pub fn metadata_list_profile(list: &[&Hdr10PlusMetadata]) -> &'static str {
+    if list.iter().all(|m| m.profile == "B") {
         "B"
     } else if list.iter().all(|m| m.profile == "A") {
         "A"
     } else {
         "N/A"
-    };
+    }
+}
 
-    let mut metadata_json_array = list
-        .iter()
-        .map(|m| {
-            // Profile A, no bezier curve data
-            if profile == "A" {
-                json!({
-                    "LuminanceParameters": {
-                        "AverageRGB": m.average_maxrgb,
-                        "LuminanceDistributions": DistributionMaxRgb::separate_json(&m.distribution_maxrgb),
-                        "MaxScl": m.maxscl
-                    },
-                    "NumberOfWindows": m.num_windows,
-                    "TargetedSystemDisplayMaximumLuminance": m.targeted_system_display_maximum_luminance
-                })
-            } else { // Profile B
-                let bc = m.bezier_curve.as_ref().expect("Invalid profile B: no Bezier curve data");
-
-                json!({
-                    "BezierCurveData": bc.to_json(),
-                    "LuminanceParameters": {
-                        "AverageRGB": m.average_maxrgb,
-                        "LuminanceDistributions": DistributionMaxRgb::separate_json(&m.distribution_maxrgb),
-                        "MaxScl": m.maxscl
-                    },
-                    "NumberOfWindows": m.num_windows,
-                    "TargetedSystemDisplayMaximumLuminance": m.targeted_system_display_maximum_luminance
-                })
-            }
-        })
-        .collect::<Vec<Value>>();
+fn frame_json_list(
+    profile: &str,
+    list: &[&Hdr10PlusMetadata],
+    scene_info: &[SceneFrameInfo],
+) -> Vec<Value> {
+    list.iter()
+        .zip(scene_info)
+        .map(|(m, scene)| frame_json(profile, m, scene))
+        .collect()
+}
+
+fn frame_json(profile: &str, m: &Hdr10PlusMetadata, scene: &SceneFrameInfo) -> Value {
+    let luminance_parameters = json!({
+        "AverageRGB": m.average_maxrgb,
+        "LuminanceDistributions": DistributionMaxRgb::separate_json(&m.distribution_maxrgb),
+        "MaxScl": m.maxscl
+    });
 
-    compute_scene_information(profile, &mut metadata_json_array);
+    // Profile A, no bezier curve data.
+    // A list with both profiles is N/A, every frame is written with its own Bezier curve
+    let bezier_curve = m.bezier_curve.as_ref().filter(|_| profile != "A");
 
-    (profile.to_string(), metadata_json_array)
+    if let Some(bc) = bezier_curve {
+        json!({
+            "BezierCurveData": bc.to_json(),
+            "LuminanceParameters": luminance_parameters,
+            "NumberOfWindows": m.num_windows,
+            "TargetedSystemDisplayMaximumLuminance": m.targeted_system_display_maximum_luminance,
+            "SceneFrameIndex": scene.scene_frame_index,
+            "SceneId": scene.scene_id,
+            "SequenceFrameIndex": scene.sequence_frame_index
+        })
+    } else {
+        json!({
+            "LuminanceParameters": luminance_parameters,
+            "NumberOfWindows": m.num_windows,
+            "TargetedSystemDisplayMaximumLuminance": m.targeted_system_display_maximum_luminance,
+            "SceneFrameIndex": scene.scene_frame_index,
+            "SceneId": scene.scene_id,
+            "SequenceFrameIndex": scene.sequence_frame_index
+        })
+    }
 }
 
+/// Inserts `SceneFrameIndex`, `SceneId` and `SequenceFrameIndex` into the JSON frames.
+///
+/// `compute_scene_frames` computes the same information from the metadata.
 pub fn compute_scene_information(profile: &str, metadata_json_array: &mut [Value]) {
     let mut scene_frame_index: u64 = 0;
     let mut scene_id: u64 = 0;
@@ -250,6 +556,222 @@ pub fn compute_scene_information(profile: &str, metadata_json_array: &mut [Value
     }
 }
 
+/// A new scene starts when any of the metadata written to the JSON changes
+pub fn compute_scene_frames(profile: &str, list: &[&Hdr10PlusMetadata]) -> Vec<SceneFrameInfo> {
+    let mut scenes = SceneTracker::default();
+
+    list.iter()
+        .enumerate()
+        .map(|(i, metadata)| {
+            let prev_metadata = i.checked_sub(1).map(|prev| list[prev]);
+            scenes.next_frame(profile, prev_metadata, metadata)
+        })
+        .collect()
+}
+
+fn same_scene(profile: &str, a: &Hdr10PlusMetadata, b: &Hdr10PlusMetadata) -> bool {
+    // Can only be different if profile B
+    let same_bezier = if profile == "B" {
+        fn bezier_data(m: &Hdr10PlusMetadata) -> Option<(u16, u16, &[u16])> {
+            m.bezier_curve.as_ref().map(|bc| {
+                (
+                    bc.knee_point_x,
+                    bc.knee_point_y,
+                    bc.bezier_curve_anchors.as_slice(),
+                )
+            })
+        }
+
+        bezier_data(a) == bezier_data(b)
+    } else {
+        true
+    };
+
+    let same_luminance = a.average_maxrgb == b.average_maxrgb
+        && a.distribution_maxrgb == b.distribution_maxrgb
+        && a.maxscl == b.maxscl;
+
+    same_bezier
+        && same_luminance
+        && a.num_windows == b.num_windows
+        && a.targeted_system_display_maximum_luminance
+            == b.targeted_system_display_maximum_luminance
+}
+
+impl SceneTracker {
+    pub fn next_frame(
+        &mut self,
+        profile: &str,
+        prev_metadata: Option<&Hdr10PlusMetadata>,
+        metadata: &Hdr10PlusMetadata,
+    ) -> SceneFrameInfo {
+        let new_scene = prev_metadata.is_some_and(|prev| !same_scene(profile, prev, metadata));
+        self.advance(new_scene)
+    }
+
+    /// Same as `next_frame`, with the scene change already known.
+    /// The first frame always starts a scene.
+    pub fn advance(&mut self, new_scene: bool) -> SceneFrameInfo {
+        if new_scene && self.frame_count > 0 {
+            self.scene_id += 1;
+            self.scene_frame_index = 0;
+        }
+
+        let frame = SceneFrameInfo {
+            scene_frame_index: self.scene_frame_index,
+            scene_id: self.scene_id,
+            sequence_frame_index: self.frame_count,
+        };
+
+        self.scene_frame_index += 1;
+        self.frame_count += 1;
+
+        frame
+    }
+}
+
+impl<W: Write> MetadataJsonWriter<W> {
+    /// Writes `JSONInfo` and opens the `SceneInfo` list
+    pub fn new(mut writer: W, profile: &str, version: u8) -> Result<Self> {
+        writer.write_all(b"{\n  \"JSONInfo\": ")?;
+        write_pretty(&mut writer, &json_info(profile, version), 2)?;
+        writer.write_all(b",\n  \"SceneInfo\": [")?;
+
+        Ok(Self {
+            writer,
+            profile: profile.to_string(),
+            scenes: SceneTracker::default(),
+            previous: None,
+            first_frames: Vec::new(),
+        })
+    }
+
+    /// Appends the next frame to the `SceneInfo` list
+    pub fn write_frame(&mut self, metadata: Hdr10PlusMetadata) -> Result<()> {
+        let scene = self
+            .scenes
+            .next_frame(&self.profile, self.previous.as_ref(), &metadata);
+
+        if scene.scene_frame_index == 0 {
+            self.first_frames.push(scene.sequence_frame_index);
+        }
+
+        let separator: &[u8] = if scene.sequence_frame_index == 0 {
+            b"\n    "
+        } else {
+            b",\n    "
+        };
+        self.writer.write_all(separator)?;
+        write_pretty(
+            &mut self.writer,
+            &frame_json(&self.profile, &metadata, &scene),
+            4,
+        )?;
+
+        self.previous = Some(metadata);
+
+        Ok(())
+    }
+
+    /// Appends a frame serialized beforehand, `new_scene` when it starts a scene for the list profile.
+    ///
+    /// Can't be mixed with `write_frame`, which compares each frame with the previous one.
+    pub fn write_serialized_frame(
+        &mut self,
+        frame: &SerializedFrame,
+        new_scene: bool,
+    ) -> Result<()> {
+        let scene = self.scenes.advance(new_scene);
+
+        if scene.scene_frame_index == 0 {
+            self.first_frames.push(scene.sequence_frame_index);
+        }
+
+        let separator: &[u8] = if scene.sequence_frame_index == 0 {
+            b"\n    "
+        } else {
+            b",\n    "
+        };
+        self.writer.write_all(separator)?;
+
+        let (head, tail) = frame.text.split_at(frame.scene_offset);
+        self.writer.write_all(head.as_bytes())?;
+        write!(
+            self.writer,
+            "\"SceneFrameIndex\": {},\n      \"SceneId\": {},\n      \"SequenceFrameIndex\": {}",
+            scene.scene_frame_index, scene.scene_id, scene.sequence_frame_index
+        )?;
+        self.writer.write_all(tail.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Closes the `SceneInfo` list and writes the summary and tool info.
+    ///
+    /// No trailing newline is written, same as `to_string_pretty`.
+    pub fn finish(mut self, tool_name: &str, tool_version: &str) -> Result<W> {
+        let frame_count = self.scenes.frame_count;
+
+        if frame_count > 0 {
+            self.writer.write_all(b"\n  ]")?;
+        } else {
+            self.writer.write_all(b"]")?;
+        }
+
+        self.writer.write_all(b",\n  \"SceneInfoSummary\": ")?;
+        let summary = scene_info_summary_json(self.first_frames, frame_count);
+        write_pretty(&mut self.writer, &summary, 2)?;
+
+        self.writer.write_all(b",\n  \"ToolInfo\": ")?;
+        write_pretty(
+            &mut self.writer,
+            &tool_info_json(tool_name, tool_version),
+            2,
+        )?;
+        self.writer.write_all(b"\n}")?;
+
+        Ok(self.writer)
+    }
+}
+
+impl SerializedFrame {
+    /// Serializes the frame as written in the `SceneInfo` list, without the scene fields.
+    ///
+    /// The Bezier curve is always written, as in a list with both profiles.
+    pub fn new(metadata: &Hdr10PlusMetadata) -> Result<Self> {
+        let mut text = Vec::new();
+        let value = frame_json("N/A", metadata, &SceneFrameInfo::default());
+        write_pretty(&mut text, &value, 4)?;
+
+        let mut text = String::from_utf8(text)?;
+        let scene_offset = text
+            .find("\"SceneFrameIndex\"")
+            .context("Missing scene fields")?;
+        let last_field = "\"SequenceFrameIndex\": 0";
+        let scene_end = text.find(last_field).context("Missing scene fields")? + last_field.len();
+        text.replace_range(scene_offset..scene_end, "");
+
+        Ok(Self { text, scene_offset })
+    }
+}
+
+/// Pretty prints a value nested at `indent` spaces, the first line is not indented
+fn write_pretty<W: Write>(writer: &mut W, value: &Value, indent: usize) -> Result<()> {
+    let pretty = serde_json::to_string_pretty(value)?;
+    let padding = " ".repeat(indent);
+
+    for (i, line) in pretty.split('\n').enumerate() {
+        if i > 0 {
+            writer.write_all(b"\n")?;
+            writer.write_all(padding.as_bytes())?;
+        }
+
+        writer.write_all(line.as_bytes())?;
+    }
+
+    Ok(())
+}
+
 impl DistributionMaxRgb {
     pub fn separate_json(list: &[Self]) -> Value {
         json!({
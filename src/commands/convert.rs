@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::{BufWriter, Write, stdout};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use hdr10plus::metadata::Hdr10PlusMetadata;
+use hdr10plus::metadata_binary::{MetadataBinaryReader, MetadataBinaryWriter};
+use hdr10plus::metadata_json::{
+    MetadataJsonReader, MetadataJsonWriter, SerializedFrame, metadata_list_profile,
+};
+
+use crate::CliOptions;
+use crate::core::metadata_file::{MetadataFormat, combined_profile};
+use crate::core::parser::{TOOL_NAME, TOOL_VERSION};
+
+use super::{ConvertArgs, input_from_either};
+
+pub struct Converter {
+    input: PathBuf,
+    output: PathBuf,
+    format: MetadataFormat,
+    options: CliOptions,
+}
+
+impl Converter {
+    pub fn convert(args: ConvertArgs, options: CliOptions) -> Result<()> {
+        let ConvertArgs {
+            input,
+            input_pos,
+            output,
+        } = args;
+
+        let input = input_from_either("convert", input, input_pos)?;
+        let format = MetadataFormat::from_file(&input)?;
+
+        let output = if let Some(output) = output {
+            output
+        } else {
+            let file_stem = input
+                .file_stem()
+                .ok_or_else(|| anyhow!("Invalid input file name: {}", input.display()))?;
+
+            PathBuf::from(format!(
+                "{}_converted.{}",
+                file_stem.to_string_lossy(),
+                match format {
+                    MetadataFormat::Json => MetadataFormat::Binary.extension(),
+                    MetadataFormat::Binary => MetadataFormat::Json.extension(),
+                }
+            ))
+        };
+
+        let converter = Converter {
+            input,
+            output,
+            format,
+            options,
+        };
+
+        match converter.format {
+            MetadataFormat::Json => converter.json_to_binary(),
+            MetadataFormat::Binary => converter.binary_to_json(),
+        }
+    }
+
+    fn create_output(&self) -> Result<BufWriter<File>> {
+        let file = File::create(&self.output)
+            .with_context(|| format!("Failed creating output file {}", self.output.display()))?;
+
+        Ok(BufWriter::with_capacity(100_000, file))
+    }
+
+    fn json_to_binary(&self) -> Result<()> {
+        print!("Converting JSON metadata to binary... ");
+        stdout().flush().ok();
+
+        let reader = MetadataJsonReader::from_file(&self.input)?;
+        let mut writer = MetadataBinaryWriter::new(
+            self.create_output()?,
+            TOOL_NAME,
+            TOOL_VERSION,
+            self.options.validate,
+        )?;
+
+        for (i, frame) in reader.enumerate() {
+            let frame = frame?;
+            let metadata = Hdr10PlusMetadata::try_from(&frame)
+                .with_context(|| format!("Invalid metadata for frame {i}"))?;
+
+            writer
+                .write_frame(&metadata, frame.scene_frame_index == 0)
+                .with_context(|| format!("Failed encoding metadata for frame {i}"))?;
+        }
+
+        writer.finish()?.flush()?;
+
+        println!("Done.");
+
+        Ok(())
+    }
+
+    fn binary_to_json(&self) -> Result<()> {
+        print!("Converting binary metadata to JSON... ");
+        stdout().flush().ok();
+
+        // The profile is written before the frames, the file is read twice
+        let (profile, version, scene_first_frames) =
+            binary_info(&self.input, self.options.validate)?;
+
+        let reader = MetadataBinaryReader::from_file(&self.input)?;
+        let mut writer = MetadataJsonWriter::new(self.create_output()?, profile, version)?;
+
+        // The scenes of the file are kept, instead of being computed again
+        let mut scene_starts = scene_first_frames.into_iter().peekable();
+
+        for (i, metadata) in reader.enumerate() {
+            let new_scene = scene_starts.next_if_eq(&(i as u32)).is_some();
+
+            writer.write_serialized_frame(&SerializedFrame::new(&metadata?)?, new_scene)?;
+        }
+
+        let mut writer = writer.finish(TOOL_NAME, TOOL_VERSION)?;
+        writeln!(writer)?;
+        writer.flush()?;
+
+        println!("Done.");
+
+        Ok(())
+    }
+}
+
+/// Profile, version and scene index of the whole binary file, validating every frame when enabled
+fn binary_info(input: &Path, validate: bool) -> Result<(&'static str, u8, Vec<u32>)> {
+    let mut profile = None;
+    let mut version = None;
+
+    let mut reader = MetadataBinaryReader::from_file(input)?;
+
+    for (i, metadata) in reader.by_ref().enumerate() {
+        let metadata = metadata?;
+
+        if validate {
+            metadata
+                .validate()
+                .with_context(|| format!("Invalid HDR10+ metadata in frame {i}"))?;
+        }
+
+        let frame_profile = metadata_list_profile(&[&metadata]);
+        profile = Some(combined_profile(profile, frame_profile));
+        version = version.or(Some(metadata.application_version));
+    }
+
+    // Read after the last frame
+    let scene_first_frames = reader.scene_first_frames().unwrap_or_default().to_vec();
+
+    Ok((
+        profile.unwrap_or("N/A"),
+        version.unwrap_or(1),
+        scene_first_frames,
+    ))
+}
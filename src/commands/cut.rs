@@ -0,0 +1,392 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write, stdout};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail, ensure};
+use indicatif::ProgressBar;
+
+use hevc_parser::io::{IoFormat, IoProcessor, NalBuffer, StartCodePreset, processor};
+use hevc_parser::{HevcParser, NALUStartCode, hevc::*};
+use processor::{HevcProcessor, HevcProcessorOpts};
+
+use hdr10plus::metadata_json::MetadataJsonWriter;
+
+use crate::commands::CutArgs;
+use crate::core::parser::{Parser, ParserOptions, TOOL_NAME, TOOL_VERSION};
+use crate::core::{OutputWriter, StartCodeTracker, initialize_progress_bar};
+
+use super::{CliOptions, input_from_either};
+
+pub struct Cutter {
+    input: PathBuf,
+    output: PathBuf,
+    json_output: PathBuf,
+    options: CliOptions,
+    range: (usize, usize),
+    preserve_start_codes: bool,
+
+    writer: OutputWriter,
+    progress_bar: ProgressBar,
+
+    /// Access units to write, from the first pass
+    plan: Option<CutPlan>,
+
+    /// Latest parameter sets before the cut, for streams that only have them at the start
+    parameter_sets: Vec<NalBuffer>,
+    wrote_parameter_sets: bool,
+
+    start_code_tracker: StartCodeTracker,
+}
+
+/// Access units kept in the output, by decoded number
+#[derive(Debug)]
+struct CutPlan {
+    first_decoded: u64,
+    last_decoded: u64,
+    /// Leading pictures of the first IRAP, presented before it
+    leading: HashSet<u64>,
+
+    /// Presentation frames of the output, inclusive
+    start: usize,
+    end: usize,
+}
+
+impl Cutter {
+    pub fn cut(args: CutArgs, options: CliOptions) -> Result<()> {
+        let CutArgs {
+            input,
+            input_pos,
+            range,
+            output,
+            json_output,
+            preserve_start_codes,
+        } = args;
+
+        let input = input_from_either("cut", input, input_pos)?;
+        let format = hevc_parser::io::format_from_path(&input)?;
+
+        if format != IoFormat::Raw {
+            bail!("Cut: Must be a raw HEVC bitstream file");
+        }
+
+        let output = output.unwrap_or_else(|| PathBuf::from("cut_output.hevc"));
+        let json_output = json_output.unwrap_or_else(|| output.with_extension("json"));
+
+        let mut cutter = Cutter {
+            progress_bar: initialize_progress_bar(&format, &input)?,
+            writer: OutputWriter::new(&output, 100_000)?,
+            input,
+            output,
+            json_output,
+            options,
+            range,
+            preserve_start_codes,
+            plan: None,
+            parameter_sets: Vec::new(),
+            wrote_parameter_sets: false,
+            start_code_tracker: StartCodeTracker::default(),
+        };
+
+        println!("Processing input video for frame order info...");
+        stdout().flush().ok();
+        cutter.process_input()?;
+
+        let Some(plan) = &cutter.plan else {
+            bail!("No frames to cut");
+        };
+
+        println!(
+            "Writing frames {}-{} ({} frames)...",
+            plan.start,
+            plan.end,
+            plan.frame_count()
+        );
+        stdout().flush().ok();
+
+        cutter.progress_bar = initialize_progress_bar(&format, &cutter.input)?;
+        cutter.process_input()?;
+
+        cutter.extract_metadata()
+    }
+
+    fn process_input(&mut self) -> Result<()> {
+        let chunk_size = 100_000;
+
+        let mut processor =
+            HevcProcessor::new(IoFormat::Raw, HevcProcessorOpts::default(), chunk_size);
+
+        let file = File::open(&self.input)?;
+        let mut reader = Box::new(BufReader::with_capacity(chunk_size, file));
+
+        processor.process_io(&mut reader, self)
+    }
+
+    /// The metadata is extracted from the cut stream, so it follows its presentation order
+    fn extract_metadata(&self) -> Result<()> {
+        let Some(plan) = &self.plan else {
+            return Ok(());
+        };
+
+        let options = CliOptions {
+            verify: false,
+            validate: self.options.validate,
+        };
+
+        let pb = initialize_progress_bar(&IoFormat::Raw, &self.output)?;
+        let mut parser = Parser::new(
+            self.output.clone(),
+            None,
+            options,
+            pb,
+            false,
+            ParserOptions {
+                quiet: true,
+                ..Default::default()
+            },
+        );
+
+        parser
+            .process_input(&IoFormat::Raw)
+            .context("Failed extracting the HDR10+ metadata of the cut")?;
+        let mut frames = parser.take_frames()?;
+
+        let first = frames
+            .next_frame()?
+            .context("No HDR10+ metadata in the cut")?;
+
+        let file = File::create(&self.json_output).with_context(|| {
+            format!("Failed creating output file {}", self.json_output.display())
+        })?;
+        let writer = BufWriter::with_capacity(1_000_000, file);
+
+        print!("Writing metadata to JSON file... ");
+        stdout().flush().ok();
+
+        let mut json_writer =
+            MetadataJsonWriter::new(writer, frames.profile(), first.application_version)?;
+        json_writer.write_frame(first)?;
+
+        while let Some(metadata) = frames.next_frame()? {
+            json_writer.write_frame(metadata)?;
+        }
+
+        ensure!(
+            frames.frame_count() == plan.frame_count(),
+            "Cut has {} frames of metadata, expected {}",
+            frames.frame_count(),
+            plan.frame_count()
+        );
+
+        let mut writer = json_writer.finish(TOOL_NAME, TOOL_VERSION)?;
+        writeln!(writer)?;
+        writer.flush()?;
+
+        println!("Done.");
+
+        Ok(())
+    }
+
+    fn write_nal(&mut self, nal: &NALUnit, data: &[u8]) -> Result<()> {
+        if self.preserve_start_codes {
+            let start_code = self.start_code_tracker.start_code(nal);
+
+            self.writer.write_all(start_code.slice())?;
+            self.writer.write_all(data)?;
+        } else {
+            NALUnit::write_with_preset(
+                &mut self.writer,
+                data,
+                StartCodePreset::Four,
+                nal.nal_type,
+                false,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Parameter sets from before the cut, when the first access unit doesn't start with its own
+    fn write_parameter_sets(&mut self, nal: &NALUnit) -> Result<()> {
+        if self.wrote_parameter_sets || nal.nal_type == NAL_AUD {
+            return Ok(());
+        }
+
+        self.wrote_parameter_sets = true;
+
+        if nal.nal_type == NAL_VPS {
+            return Ok(());
+        }
+
+        for nb in std::mem::take(&mut self.parameter_sets) {
+            let start_code = if self.preserve_start_codes {
+                nb.start_code
+            } else {
+                NALUStartCode::Length4
+            };
+
+            self.writer.write_all(start_code.slice())?;
+            self.writer.write_all(&nb.data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the parameter sets of the latest VPS, as they are replaced
+    fn remember_parameter_set(&mut self, nal: &NALUnit, chunk: &[u8]) {
+        if !matches!(nal.nal_type, NAL_VPS | NAL_SPS | NAL_PPS) || nal.nuh_layer_id > 0 {
+            return;
+        }
+
+        if nal.nal_type == NAL_VPS {
+            self.parameter_sets.clear();
+        }
+
+        self.parameter_sets.push(NalBuffer {
+            nal_type: nal.nal_type,
+            start_code: nal.start_code,
+            data: chunk[nal.start..nal.end].to_vec(),
+        });
+    }
+}
+
+impl CutPlan {
+    /// Starts at the nearest IRAP at or before the start frame.
+    ///
+    /// The access units are cut in decode order, so the end is extended
+    /// until the kept frames are contiguous in presentation order.
+    fn new(frames: &[Frame], (start, end): (usize, usize)) -> Result<Self> {
+        ensure!(
+            end < frames.len(),
+            "Invalid range {start}-{end}, the video has {} frames",
+            frames.len()
+        );
+
+        let mut presentation_numbers = vec![0; frames.len()];
+        for frame in frames {
+            let decoded = frame.decoded_number as usize;
+            ensure!(
+                decoded < frames.len(),
+                "Invalid decoded frame number {decoded}"
+            );
+
+            presentation_numbers[decoded] = frame.presentation_number as usize;
+        }
+
+        let irap = frames
+            .iter()
+            .filter(|f| f.first_slice.key_frame && f.presentation_number as usize <= start)
+            .max_by_key(|f| f.presentation_number)
+            .with_context(|| format!("No IRAP frame found before frame {start}"))?;
+
+        let first_decoded = irap.decoded_number;
+        let first = irap.presentation_number as usize;
+
+        let mut leading = HashSet::new();
+        let mut last = first;
+        let mut kept = 0;
+        let mut last_decoded = first_decoded;
+
+        for (decoded, &presentation) in presentation_numbers
+            .iter()
+            .enumerate()
+            .skip(first_decoded as usize)
+        {
+            last_decoded = decoded as u64;
+
+            if presentation < first {
+                leading.insert(last_decoded);
+                continue;
+            }
+
+            kept += 1;
+            last = last.max(presentation);
+
+            if last >= end && kept == last - first + 1 {
+                break;
+            }
+        }
+
+        Ok(Self {
+            first_decoded,
+            last_decoded,
+            leading,
+            start: first,
+            end: last,
+        })
+    }
+
+    fn contains(&self, decoded_index: u64) -> bool {
+        (self.first_decoded..=self.last_decoded).contains(&decoded_index)
+            && !self.leading.contains(&decoded_index)
+    }
+
+    fn frame_count(&self) -> usize {
+        self.end - self.start + 1
+    }
+}
+
+impl IoProcessor for Cutter {
+    fn input(&self) -> &PathBuf {
+        &self.input
+    }
+
+    fn update_progress(&mut self, delta: u64) {
+        self.progress_bar.inc(delta);
+    }
+
+    fn process_nals(&mut self, _parser: &HevcParser, nals: &[NALUnit], chunk: &[u8]) -> Result<()> {
+        // Only the frame order is needed in the first pass
+        let Some(first_decoded) = self.plan.as_ref().map(|p| p.first_decoded) else {
+            return Ok(());
+        };
+
+        for nal in nals {
+            let decoded_index = nal.decoded_frame_index;
+
+            if decoded_index < first_decoded {
+                self.remember_parameter_set(nal, chunk);
+            } else if self
+                .plan
+                .as_ref()
+                .is_some_and(|p| p.contains(decoded_index))
+            {
+                self.write_parameter_sets(nal)?;
+                self.write_nal(nal, &chunk[nal.start..nal.end])?;
+            }
+        }
+
+        self.start_code_tracker.finish_chunk(nals, chunk);
+
+        Ok(())
+    }
+
+    fn finalize(&mut self, parser: &HevcParser) -> Result<()> {
+        self.progress_bar.finish_and_clear();
+
+        if self.plan.is_none() {
+            let (start, end) = self.range;
+            let plan = CutPlan::new(parser.ordered_frames(), self.range)?;
+
+            if plan.start != start {
+                println!(
+                    "Warning: frame {start} is not an IRAP, the cut starts at the previous IRAP frame {}",
+                    plan.start
+                );
+            }
+
+            if plan.end != end {
+                println!(
+                    "Warning: the cut ends at frame {}, the frames up to it are decoded before frame {end}",
+                    plan.end
+                );
+            }
+
+            self.plan = Some(plan);
+        } else {
+            self.writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
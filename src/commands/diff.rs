@@ -0,0 +1,495 @@
+use anyhow::{Result, ensure};
+
+use hdr10plus::metadata::Hdr10PlusMetadata;
+
+use super::{CliOptions, DiffArgs};
+use crate::core::metadata_file::MetadataReader;
+
+/// Maximum amount of differing frame ranges printed
+const MAX_REPORTED_RANGES: usize = 20;
+
+/// Above this amount of scene pairs, insertions and deletions are not detected
+const MAX_SCENE_PAIRS: usize = 25_000_000;
+
+pub struct Differ {
+    offset: i64,
+    /// In nits
+    tolerance: f64,
+
+    /// Consecutive differing frames, with the same differing fields
+    ranges: Vec<DiffRange>,
+    differing_frames: usize,
+}
+
+#[derive(Debug)]
+struct DiffRange {
+    /// Frame numbers in the first source, inclusive
+    start: usize,
+    end: usize,
+    /// Offset applied to get the frame numbers in the second source
+    offset: i64,
+
+    /// Differences of the first frame of the range
+    fields: Vec<FieldDiff>,
+}
+
+#[derive(Debug)]
+struct FieldDiff {
+    name: &'static str,
+    first: String,
+    second: String,
+}
+
+/// Frame range of a scene
+struct Scene {
+    index: usize,
+    start: usize,
+    length: usize,
+}
+
+impl Differ {
+    pub fn diff(args: DiffArgs, options: CliOptions) -> Result<()> {
+        let DiffArgs {
+            first,
+            second,
+            offset,
+            tolerance,
+        } = args;
+
+        ensure!(tolerance >= 0.0, "Tolerance must not be negative");
+
+        // Both sources are read with the same options
+        let mut first_reader = MetadataReader::from_source(&first, options.clone())?;
+        let mut second_reader = MetadataReader::from_source(&second, options)?;
+
+        let mut differ = Differ {
+            offset,
+            tolerance,
+            ranges: Vec::new(),
+            differing_frames: 0,
+        };
+
+        println!("Comparing {} and {}", first.display(), second.display());
+
+        let (first_frames, second_frames) =
+            differ.compare_frames(&mut first_reader, &mut second_reader)?;
+
+        println!("Frames: {first_frames} in first, {second_frames} in second");
+
+        let scenes_changed = report_scene_changes(&first_reader, &second_reader);
+        differ.report_frames();
+
+        if !scenes_changed && differ.ranges.is_empty() && first_frames == second_frames {
+            println!("No differences found.");
+        }
+
+        Ok(())
+    }
+
+    /// Returns the frame count of both sources
+    fn compare_frames(
+        &mut self,
+        first: &mut MetadataReader,
+        second: &mut MetadataReader,
+    ) -> Result<(usize, usize)> {
+        // Frames without a counterpart in the other source
+        let skipped = self.offset.unsigned_abs();
+        let skipped_reader = if self.offset > 0 {
+            &mut *second
+        } else {
+            &mut *first
+        };
+
+        for _ in 0..skipped {
+            if skipped_reader.next_frame()?.is_none() {
+                break;
+            }
+        }
+
+        let mut frame = first.frame_count();
+
+        loop {
+            let (a, b) = match (first.next_frame()?, second.next_frame()?) {
+                (Some(a), Some(b)) => (a, b),
+                (None, None) => break,
+                // The remaining frames are only counted
+                _ => continue,
+            };
+
+            let fields = field_differences(&a, &b, self.tolerance);
+
+            if !fields.is_empty() {
+                self.add_differing_frame(frame, fields);
+            }
+
+            frame += 1;
+        }
+
+        Ok((first.frame_count(), second.frame_count()))
+    }
+
+    fn add_differing_frame(&mut self, frame: usize, fields: Vec<FieldDiff>) {
+        self.differing_frames += 1;
+
+        if let Some(range) = self.ranges.last_mut() {
+            let same_fields = range.fields.len() == fields.len()
+                && range
+                    .fields
+                    .iter()
+                    .zip(&fields)
+                    .all(|(a, b)| a.name == b.name);
+
+            if range.end + 1 == frame && same_fields {
+                range.end = frame;
+                return;
+            }
+        }
+
+        self.ranges.push(DiffRange {
+            start: frame,
+            end: frame,
+            offset: self.offset,
+            fields,
+        });
+    }
+
+    fn report_frames(&self) {
+        if self.ranges.is_empty() {
+            return;
+        }
+
+        println!(
+            "{} frames differ, in {} ranges:",
+            self.differing_frames,
+            self.ranges.len()
+        );
+
+        for range in self.ranges.iter().take(MAX_REPORTED_RANGES) {
+            range.print();
+        }
+
+        if self.ranges.len() > MAX_REPORTED_RANGES {
+            println!("  ... and {} more", self.ranges.len() - MAX_REPORTED_RANGES);
+        }
+    }
+}
+
+impl DiffRange {
+    fn print(&self) {
+        let second_start = self.start as i64 + self.offset;
+        let second_end = self.end as i64 + self.offset;
+
+        let frames = if self.start == self.end {
+            format!("Frame {}", self.start)
+        } else {
+            format!("Frames {}-{}", self.start, self.end)
+        };
+        let second_frames = if self.offset == 0 {
+            String::new()
+        } else if self.start == self.end {
+            format!(" (second: {second_start})")
+        } else {
+            format!(" (second: {second_start}-{second_end})")
+        };
+
+        println!("  {frames}{second_frames}:");
+
+        for field in &self.fields {
+            println!("    {}: {} -> {}", field.name, field.first, field.second);
+        }
+    }
+}
+
+/// Fields that differ between the two frames.
+/// The luminance values are compared with the tolerance, in nits.
+fn field_differences(
+    a: &Hdr10PlusMetadata,
+    b: &Hdr10PlusMetadata,
+    tolerance: f64,
+) -> Vec<FieldDiff> {
+    // Most luminance values are in units of 0.1 nits
+    let within = |a: u32, b: u32, scale: f64| (a as f64 - b as f64).abs() / scale <= tolerance;
+
+    let mut diffs = Vec::new();
+    let mut push = |name: &'static str, first: String, second: String| {
+        diffs.push(FieldDiff {
+            name,
+            first,
+            second,
+        })
+    };
+
+    if a.application_version != b.application_version {
+        push(
+            "application_version",
+            a.application_version.to_string(),
+            b.application_version.to_string(),
+        );
+    }
+
+    if a.num_windows != b.num_windows || a.processing_windows != b.processing_windows {
+        push(
+            "processing_windows",
+            format!("{} {:?}", a.num_windows, a.processing_windows),
+            format!("{} {:?}", b.num_windows, b.processing_windows),
+        );
+    }
+
+    if !within(
+        a.targeted_system_display_maximum_luminance,
+        b.targeted_system_display_maximum_luminance,
+        1.0,
+    ) {
+        push(
+            "targeted_system_display_maximum_luminance",
+            a.targeted_system_display_maximum_luminance.to_string(),
+            b.targeted_system_display_maximum_luminance.to_string(),
+        );
+    }
+
+    if a.actual_targeted_system_display != b.actual_targeted_system_display {
+        push(
+            "actual_targeted_system_display",
+            format!("{:?}", a.actual_targeted_system_display),
+            format!("{:?}", b.actual_targeted_system_display),
+        );
+    }
+
+    if !a
+        .maxscl
+        .iter()
+        .zip(&b.maxscl)
+        .all(|(x, y)| within(*x, *y, 10.0))
+    {
+        push(
+            "maxscl",
+            format!("{:?}", a.maxscl),
+            format!("{:?}", b.maxscl),
+        );
+    }
+
+    if !within(a.average_maxrgb, b.average_maxrgb, 10.0) {
+        push(
+            "average_maxrgb",
+            a.average_maxrgb.to_string(),
+            b.average_maxrgb.to_string(),
+        );
+    }
+
+    let percentages = |m: &Hdr10PlusMetadata| -> Vec<u8> {
+        m.distribution_maxrgb.iter().map(|d| d.percentage).collect()
+    };
+    let percentiles = |m: &Hdr10PlusMetadata| -> Vec<u32> {
+        m.distribution_maxrgb.iter().map(|d| d.percentile).collect()
+    };
+
+    if percentages(a) != percentages(b) {
+        push(
+            "distribution_index",
+            format!("{:?}", percentages(a)),
+            format!("{:?}", percentages(b)),
+        );
+    } else if !percentiles(a)
+        .iter()
+        .zip(&percentiles(b))
+        .all(|(x, y)| within(*x, *y, 10.0))
+    {
+        push(
+            "distribution_values",
+            format!("{:?}", percentiles(a)),
+            format!("{:?}", percentiles(b)),
+        );
+    }
+
+    if a.fraction_bright_pixels != b.fraction_bright_pixels {
+        push(
+            "fraction_bright_pixels",
+            a.fraction_bright_pixels.to_string(),
+            b.fraction_bright_pixels.to_string(),
+        );
+    }
+
+    if a.actual_mastering_display != b.actual_mastering_display {
+        push(
+            "actual_mastering_display",
+            format!("{:?}", a.actual_mastering_display),
+            format!("{:?}", b.actual_mastering_display),
+        );
+    }
+
+    let knee_point = |m: &Hdr10PlusMetadata| {
+        m.bezier_curve
+            .as_ref()
+            .map(|bc| (bc.knee_point_x, bc.knee_point_y))
+    };
+    let anchors = |m: &Hdr10PlusMetadata| {
+        m.bezier_curve
+            .as_ref()
+            .map(|bc| bc.bezier_curve_anchors.clone())
+    };
+
+    if knee_point(a) != knee_point(b) {
+        push(
+            "knee_point",
+            format!("{:?}", knee_point(a)),
+            format!("{:?}", knee_point(b)),
+        );
+    }
+
+    if anchors(a) != anchors(b) {
+        push(
+            "bezier_curve_anchors",
+            format!("{:?}", anchors(a)),
+            format!("{:?}", anchors(b)),
+        );
+    }
+
+    if a.color_saturation_mapping_flag != b.color_saturation_mapping_flag
+        || a.color_saturation_weight != b.color_saturation_weight
+    {
+        push(
+            "color_saturation_weight",
+            format!(
+                "{:?}",
+                a.color_saturation_mapping_flag
+                    .then_some(a.color_saturation_weight)
+            ),
+            format!(
+                "{:?}",
+                b.color_saturation_mapping_flag
+                    .then_some(b.color_saturation_weight)
+            ),
+        );
+    }
+
+    diffs
+}
+
+/// Reports the scenes inserted or deleted, by matching the scene lengths of both sources.
+/// Returns whether any was found.
+fn report_scene_changes(first: &MetadataReader, second: &MetadataReader) -> bool {
+    let (Some(first_scenes), Some(second_scenes)) = (
+        scene_list(first.scene_first_frames(), first.frame_count()),
+        scene_list(second.scene_first_frames(), second.frame_count()),
+    ) else {
+        return false;
+    };
+
+    let first_lengths: Vec<usize> = first_scenes.iter().map(|s| s.length).collect();
+    let second_lengths: Vec<usize> = second_scenes.iter().map(|s| s.length).collect();
+
+    let Some((deleted, inserted)) = unmatched_scenes(&first_lengths, &second_lengths) else {
+        println!("Warning: Too many different scenes to detect scene insertions and deletions.");
+        return true;
+    };
+
+    if deleted.is_empty() && inserted.is_empty() {
+        return false;
+    }
+
+    println!(
+        "Scenes: {} in first, {} in second",
+        first_scenes.len(),
+        second_scenes.len()
+    );
+
+    for i in deleted {
+        println!("  Deleted from first: {}", first_scenes[i]);
+    }
+
+    for i in inserted {
+        println!("  Inserted in second: {}", second_scenes[i]);
+    }
+
+    true
+}
+
+fn scene_list(first_frames: Option<Vec<usize>>, frame_count: usize) -> Option<Vec<Scene>> {
+    let first_frames = first_frames?;
+
+    let scenes = first_frames
+        .iter()
+        .enumerate()
+        .map(|(index, &start)| {
+            let end = first_frames.get(index + 1).copied().unwrap_or(frame_count);
+
+            Scene {
+                index,
+                start,
+                length: end.saturating_sub(start),
+            }
+        })
+        .collect();
+
+    Some(scenes)
+}
+
+/// Indices of the scenes not part of the longest common subsequence of scene lengths,
+/// for the first and second list.
+///
+/// `None` when the lists differ too much to be compared
+fn unmatched_scenes(first: &[usize], second: &[usize]) -> Option<(Vec<usize>, Vec<usize>)> {
+    // Only the middle part differs, usually
+    let prefix = first.iter().zip(second).take_while(|(a, b)| a == b).count();
+    let suffix = first[prefix..]
+        .iter()
+        .rev()
+        .zip(second[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let a = &first[prefix..first.len() - suffix];
+    let b = &second[prefix..second.len() - suffix];
+
+    if a.len().saturating_mul(b.len()) > MAX_SCENE_PAIRS {
+        return None;
+    }
+
+    // Length of the common subsequence of a[i..] and b[j..]
+    let width = b.len() + 1;
+    let mut lcs = vec![0_u32; (a.len() + 1) * width];
+
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i * width + j] = if a[i] == b[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut deleted, mut inserted) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+            deleted.push(prefix + i);
+            i += 1;
+        } else {
+            inserted.push(prefix + j);
+            j += 1;
+        }
+    }
+
+    deleted.extend((i..a.len()).map(|i| prefix + i));
+    inserted.extend((j..b.len()).map(|j| prefix + j));
+
+    Some((deleted, inserted))
+}
+
+impl std::fmt::Display for Scene {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "scene {}, frames {}-{} ({} frames)",
+            self.index,
+            self.start,
+            self.start + self.length.saturating_sub(1),
+            self.length
+        )
+    }
+}
@@ -1,14 +1,22 @@
+use std::collections::{BTreeSet, HashSet};
 use std::fs::File;
 use std::io::{BufWriter, Write, stdout};
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
 
-use anyhow::{Result, bail, ensure};
-use serde::{Deserialize, Serialize};
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use serde::{Deserialize, Deserializer, Serialize};
 
-use hdr10plus::metadata::Hdr10PlusMetadata;
-use hdr10plus::metadata_json::{MetadataJsonRoot, generate_json};
+use hdr10plus::metadata::{BezierCurve, Hdr10PlusMetadata};
+use hdr10plus::metadata_binary::MetadataBinaryWriter;
+use hdr10plus::metadata_json::{compute_scene_frames, generate_json, metadata_list_profile};
 
 use crate::commands::EditorArgs;
+use crate::core::metadata_file::{MetadataFormat, MetadataReader, convert_to_profile_a};
+use crate::core::vui::VuiFrameRate;
+use crate::utils::{
+    nits_to_pq, parse_frame_rate, parse_timecode, parse_timestamp, pq_to_nits, timestamp_to_frame,
+};
 
 pub const TOOL_NAME: &str = env!("CARGO_PKG_NAME");
 pub const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -20,6 +28,8 @@ pub struct Editor {
     output: PathBuf,
 
     metadata_list: Vec<Option<Hdr10PlusMetadata>>,
+    /// First frame of every scene of the input, by `SceneId`
+    scene_first_frames: Vec<usize>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -27,18 +37,260 @@ pub struct EditConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     remove: Option<Vec<String>>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remove_scenes: Option<Vec<String>>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     duplicate: Option<Vec<DuplicateMetadata>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replace_scene: Option<Vec<ReplaceScene>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    constant_scene: Option<Vec<ConstantScene>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    merge_scenes: Option<Vec<MergeScenes>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    smooth: Option<Vec<SmoothMetadata>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale: Option<Vec<ScaleBrightness>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pq_offset: Option<Vec<PqOffsetBrightness>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clamp: Option<Vec<ClampBrightness>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile_a: Option<Vec<ProfileA>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile_b: Option<Vec<ProfileB>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_luminance: Option<Vec<TargetLuminance>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bezier_curve: Option<Vec<BezierCurveEdit>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame_rate: Option<FrameRateConversion>,
+
+    /// Frame rate of the timecodes and timestamps, from the command line
+    #[serde(skip)]
+    fps: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct DuplicateMetadata {
-    source: usize,
-    offset: usize,
+    source: FramePosition,
+    offset: FramePosition,
     length: usize,
 }
 
+/// Remaps the frames to another frame rate, after all the other edits
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FrameRateConversion {
+    /// Frame rate of the metadata, `--fps` when not set
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_frame_rate"
+    )]
+    source: Option<f64>,
+    #[serde(deserialize_with = "deserialize_frame_rate")]
+    target: f64,
+    #[serde(default)]
+    mode: FrameRateMode,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameRateMode {
+    /// Frames displayed at the same time, duplicated or dropped
+    #[default]
+    Resample,
+    /// 2:3 telecine, 4 frames to 5 interlaced frames, or the inverse
+    Pulldown,
+}
+
+/// Frame index, or a timecode or timestamp converted to a frame with the frame rate
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum FramePosition {
+    Index(usize),
+    Time(String),
+}
+
+/// Replaces the metadata of scenes with the metadata of another scene
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ReplaceScene {
+    scenes: String,
+    source: usize,
+}
+
+/// Makes each scene constant, from the statistics of its frames
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConstantScene {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenes: Option<String>,
+    #[serde(default)]
+    mode: SceneStatistic,
+}
+
+/// Merges adjacent scenes into one constant scene
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MergeScenes {
+    scenes: String,
+    #[serde(default)]
+    mode: SceneStatistic,
+}
+
+/// How the luminance statistics of a scene's frames are combined
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SceneStatistic {
+    #[default]
+    Max,
+    Average,
+}
+
+/// Temporal filter of the luminance statistics and Bezier curve anchors, reset at the scene cuts
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SmoothMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenes: Option<String>,
+
+    #[serde(default)]
+    method: SmoothingMethod,
+    /// Frames of the moving average and median windows, centered on the frame
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window: Option<usize>,
+    /// Weight of the current frame for the exponential filter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alpha: Option<f64>,
+
+    /// Reset at the scene cuts of the input's scene information
+    #[serde(default = "default_scene_cuts")]
+    scene_cuts: bool,
+    /// AverageMaxRGB jump in PQ, from which frames are also treated as cuts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cut_threshold: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmoothingMethod {
+    #[default]
+    Average,
+    Exponential,
+    Median,
+}
+
+/// Multiplies the luminance statistics of the frames
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScaleBrightness {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenes: Option<String>,
+    factor: f64,
+}
+
+/// Adds an offset to the luminance statistics of the frames, in PQ code values
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PqOffsetBrightness {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenes: Option<String>,
+    offset: f64,
+}
+
+/// Limits the luminance statistics of the frames to a maximum
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ClampBrightness {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenes: Option<String>,
+    max_nits: f64,
+}
+
+/// Converts the frames to profile A, removing the Bezier curve and target display
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileA {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenes: Option<String>,
+}
+
+/// Converts the frames to profile B, with the Bezier curve and target display given
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileB {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenes: Option<String>,
+
+    target_luminance: u32,
+    knee_point_x: u16,
+    knee_point_y: u16,
+    anchors: Vec<u16>,
+}
+
+/// Sets `targeted_system_display_maximum_luminance` of profile B frames
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TargetLuminance {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenes: Option<String>,
+
+    nits: u32,
+}
+
+/// Replaces the Bezier curve of profile B frames
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BezierCurveEdit {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenes: Option<String>,
+
+    knee_point_x: u16,
+    knee_point_y: u16,
+    anchors: Vec<u16>,
+}
+
+/// Highest luminance value allowed by the metadata validation, in 0.1 nits
+const MAX_LUMINANCE_VALUE: u32 = 100_000;
+
+/// The 5% entry of the distribution is not a brightness percentile in HDR10+ metadata
+const NON_LUMINANCE_PERCENTAGE: u8 = 5;
+
+const DEFAULT_SMOOTHING_WINDOW: usize = 5;
+const DEFAULT_SMOOTHING_ALPHA: f64 = 0.3;
+
 impl Editor {
     pub fn edit(args: EditorArgs) -> Result<()> {
         let EditorArgs {
@@ -46,29 +298,60 @@ impl Editor {
             input_pos,
             edits_json,
             json_out,
+            fps,
+            input_hevc,
         } = args;
 
         let input = input_from_either("editor", input, input_pos)?;
 
+        let fps = match input_hevc {
+            Some(input_hevc) => {
+                let fps = VuiFrameRate::read(&input_hevc)?;
+                println!("Frame rate from the HEVC file: {fps:.3}");
+
+                Some(fps)
+            }
+            None => fps,
+        };
+
+        println!("Parsing metadata file...");
+        let mut reader = MetadataReader::from_file(&input)?;
+
+        // The output is written in the same format as the input
+        let format = MetadataFormat::from_file(&input)?;
+
         let out_path = if let Some(out_path) = json_out {
             out_path
         } else {
+            let file_stem = input
+                .file_stem()
+                .ok_or_else(|| anyhow!("Invalid input file name: {}", input.display()))?;
+
             PathBuf::from(format!(
-                "{}{}",
-                input.file_stem().unwrap().to_str().unwrap(),
-                "_modified.json"
+                "{}_modified.{}",
+                file_stem.to_string_lossy(),
+                format.extension()
             ))
         };
 
-        println!("Parsing JSON file...");
-        let metadata_json_root = MetadataJsonRoot::from_file(&input)?;
-        let metadata_list: Vec<Hdr10PlusMetadata> = metadata_json_root
-            .scene_info
-            .iter()
-            .map(Hdr10PlusMetadata::try_from)
-            .filter_map(Result::ok)
-            .collect();
-        ensure!(metadata_json_root.scene_info.len() == metadata_list.len());
+        let mut metadata_list = Vec::new();
+        while let Some(metadata) = reader.next_frame()? {
+            metadata_list.push(metadata);
+        }
+
+        let scene_first_frames = match reader.scene_first_frames() {
+            Some(scene_first_frames) => scene_first_frames,
+            None => {
+                let list: Vec<&Hdr10PlusMetadata> = metadata_list.iter().collect();
+                let profile = metadata_list_profile(&list);
+
+                compute_scene_frames(profile, &list)
+                    .iter()
+                    .filter(|scene| scene.scene_frame_index == 0)
+                    .map(|scene| scene.sequence_frame_index)
+                    .collect()
+            }
+        };
 
         let metadata_list: Vec<Option<Hdr10PlusMetadata>> =
             metadata_list.into_iter().map(Some).collect();
@@ -78,26 +361,37 @@ impl Editor {
             output: out_path,
 
             metadata_list,
+            scene_first_frames,
         };
 
-        let config: EditConfig = EditConfig::from_path(&editor.edits_json)?;
+        let config: EditConfig = EditConfig::from_path(&editor.edits_json, fps)?;
 
         println!("EditConfig {}", serde_json::to_string_pretty(&config)?);
 
-        config.execute(&mut editor.metadata_list)?;
+        config.execute(&mut editor.metadata_list, &editor.scene_first_frames)?;
 
-        let save_file = File::create(editor.output).expect("Can't create file");
+        let save_file = File::create(&editor.output)
+            .with_context(|| format!("Failed creating output file {}", editor.output.display()))?;
         let mut writer = BufWriter::with_capacity(10_000_000, save_file);
 
-        print!("Generating and writing metadata to JSON file... ");
-        stdout().flush().ok();
-
         let list = config.finalize(&mut editor.metadata_list)?;
         let list: Vec<&Hdr10PlusMetadata> = list.iter().collect();
 
-        let final_json = generate_json(&list, TOOL_NAME, TOOL_VERSION);
+        match format {
+            MetadataFormat::Json => {
+                print!("Generating and writing metadata to JSON file... ");
+                stdout().flush().ok();
 
-        writeln!(writer, "{}", serde_json::to_string_pretty(&final_json)?)?;
+                let final_json = generate_json(&list, TOOL_NAME, TOOL_VERSION);
+                writeln!(writer, "{}", serde_json::to_string_pretty(&final_json)?)?;
+            }
+            MetadataFormat::Binary => {
+                print!("Writing metadata to binary file... ");
+                stdout().flush().ok();
+
+                write_binary(writer.by_ref(), &list)?;
+            }
+        }
 
         println!("Done.");
 
@@ -107,25 +401,201 @@ impl Editor {
     }
 }
 
+/// Scenes are computed the same way as for the JSON
+fn write_binary(writer: &mut BufWriter<File>, list: &[&Hdr10PlusMetadata]) -> Result<()> {
+    let profile = metadata_list_profile(list);
+    let scene_info = compute_scene_frames(profile, list);
+
+    // Same as the JSON output, the metadata is not validated
+    let mut binary_writer = MetadataBinaryWriter::new(writer, TOOL_NAME, TOOL_VERSION, false)?;
+
+    for (metadata, scene) in list.iter().zip(scene_info) {
+        binary_writer.write_frame(metadata, scene.scene_frame_index == 0)?;
+    }
+
+    binary_writer.finish()?;
+
+    Ok(())
+}
+
 impl EditConfig {
-    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+    pub fn from_path<P: AsRef<Path>>(path: P, fps: Option<f64>) -> Result<Self> {
         let json_file = File::open(path)?;
         let mut config: EditConfig = serde_json::from_reader(&json_file)?;
+        config.fps = fps;
+
+        if let Some(to_duplicate) = config.duplicate.take() {
+            let mut to_duplicate = to_duplicate
+                .into_iter()
+                .map(|meta| Ok((meta.offset.frame(fps)?, meta)))
+                .collect::<Result<Vec<_>>>()?;
 
-        if let Some(to_duplicate) = config.duplicate.as_mut() {
-            to_duplicate.sort_by_key(|meta| meta.offset);
+            to_duplicate.sort_by_key(|(offset, _)| *offset);
             to_duplicate.reverse();
+
+            config.duplicate = Some(to_duplicate.into_iter().map(|(_, meta)| meta).collect());
         }
 
         Ok(config)
     }
 
-    fn execute(&self, metadata_list: &mut [Option<Hdr10PlusMetadata>]) -> Result<()> {
+    fn execute(
+        &self,
+        metadata_list: &mut [Option<Hdr10PlusMetadata>],
+        scene_first_frames: &[usize],
+    ) -> Result<()> {
+        // Edits use the frame numbers and scenes of the input, before removing frames
+        self.execute_scene_edits(metadata_list, scene_first_frames)?;
+
+        if let Some(edits) = &self.smooth {
+            for edit in edits {
+                self.smooth(edit, metadata_list, scene_first_frames)?;
+            }
+        }
+
+        let len = metadata_list.len();
+        let frames = |range: Option<&str>, scenes: Option<&str>| {
+            self.target_frames(range, scenes, len, scene_first_frames)
+        };
+
+        if let Some(edits) = &self.scale {
+            for edit in edits {
+                ensure!(
+                    edit.factor.is_finite() && edit.factor >= 0.0,
+                    "invalid scale factor {}",
+                    edit.factor
+                );
+
+                let ranges = frames(edit.range.as_deref(), edit.scenes.as_deref())?;
+                let amount = map_brightness(metadata_list, ranges, |nits| nits * edit.factor)?;
+
+                println!("Scaled brightness of {amount} frames by {}.", edit.factor);
+            }
+        }
+
+        if let Some(edits) = &self.pq_offset {
+            for edit in edits {
+                ensure!(
+                    edit.offset.is_finite() && (-1.0..=1.0).contains(&edit.offset),
+                    "invalid PQ offset {}, should be between -1 and 1",
+                    edit.offset
+                );
+
+                let ranges = frames(edit.range.as_deref(), edit.scenes.as_deref())?;
+                let amount = map_brightness(metadata_list, ranges, |nits| {
+                    pq_to_nits((nits_to_pq(nits) + edit.offset).clamp(0.0, 1.0))
+                })?;
+
+                println!(
+                    "Offset brightness of {amount} frames by {} PQ.",
+                    edit.offset
+                );
+            }
+        }
+
+        if let Some(edits) = &self.clamp {
+            for edit in edits {
+                ensure!(
+                    edit.max_nits.is_finite() && edit.max_nits >= 0.0,
+                    "invalid clamp maximum {}",
+                    edit.max_nits
+                );
+
+                let ranges = frames(edit.range.as_deref(), edit.scenes.as_deref())?;
+                let amount = map_brightness(metadata_list, ranges, |nits| nits.min(edit.max_nits))?;
+
+                println!(
+                    "Clamped brightness of {amount} frames to {} nits.",
+                    edit.max_nits
+                );
+            }
+        }
+
+        self.execute_profile_edits(metadata_list, scene_first_frames)?;
+
         // Drop metadata frames
         if let Some(ranges) = &self.remove {
             self.remove_frames(ranges, metadata_list)?;
         }
 
+        if let Some(scenes) = &self.remove_scenes {
+            self.remove_scenes(scenes, metadata_list, scene_first_frames)?;
+        }
+
+        Ok(())
+    }
+
+    /// Scene replacements first, then the constant scenes and merges
+    fn execute_scene_edits(
+        &self,
+        metadata_list: &mut [Option<Hdr10PlusMetadata>],
+        scene_first_frames: &[usize],
+    ) -> Result<()> {
+        let len = metadata_list.len();
+
+        if let Some(edits) = &self.replace_scene {
+            for edit in edits {
+                let source_range =
+                    Self::scene_ranges(&edit.source.to_string(), len, scene_first_frames)?
+                        .remove(0);
+                let source: Vec<Option<Hdr10PlusMetadata>> = metadata_list[source_range].to_vec();
+
+                let scenes = Self::scene_ranges(&edit.scenes, len, scene_first_frames)?;
+
+                // Frames past the end of the source scene reuse its last frame
+                for range in &scenes {
+                    for (i, frame) in range.clone().enumerate() {
+                        metadata_list[frame].clone_from(&source[i.min(source.len() - 1)]);
+                    }
+                }
+
+                println!(
+                    "Replaced the metadata of {} scenes with scene {}.",
+                    scenes.len(),
+                    edit.source
+                );
+            }
+        }
+
+        if let Some(edits) = &self.constant_scene {
+            for edit in edits {
+                let scenes = match &edit.scenes {
+                    Some(scenes) => Self::scene_ranges(scenes, len, scene_first_frames)?,
+                    None => Self::scene_ranges(
+                        &format!("0-{}", scene_first_frames.len().saturating_sub(1)),
+                        len,
+                        scene_first_frames,
+                    )?,
+                };
+
+                for range in &scenes {
+                    make_constant(&mut metadata_list[range.clone()], edit.mode);
+                }
+
+                println!("Made {} scenes constant.", scenes.len());
+            }
+        }
+
+        if let Some(edits) = &self.merge_scenes {
+            for edit in edits {
+                let scenes = Self::scene_ranges(&edit.scenes, len, scene_first_frames)?;
+
+                ensure!(
+                    scenes.windows(2).all(|w| *w[0].end() + 1 == *w[1].start()),
+                    "invalid scenes to merge {}, they must be adjacent",
+                    edit.scenes
+                );
+
+                let (Some(first), Some(last)) = (scenes.first(), scenes.last()) else {
+                    continue;
+                };
+
+                make_constant(&mut metadata_list[*first.start()..=*last.end()], edit.mode);
+
+                println!("Merged {} scenes.", scenes.len());
+            }
+        }
+
         Ok(())
     }
 
@@ -140,31 +610,289 @@ impl EditConfig {
             self.duplicate_metadata(to_duplicate, &mut list)?;
         }
 
+        if let Some(conversion) = &self.frame_rate {
+            list = self.convert_frame_rate(conversion, list)?;
+        }
+
         Ok(list)
     }
 
-    fn range_string_to_tuple(range: &str) -> Result<(usize, usize)> {
-        let mut result = (0, 0);
+    fn range_string_to_tuple(range: &str, fps: Option<f64>) -> Result<(usize, usize)> {
+        match range.split_once('-') {
+            Some((start, end)) => Ok((parse_frame(start, fps)?, parse_frame(end, fps)?)),
+            None => bail!("Invalid edit range"),
+        }
+    }
+
+    /// Profile conversions first, then the target display and Bezier curve edits
+    fn execute_profile_edits(
+        &self,
+        metadata_list: &mut [Option<Hdr10PlusMetadata>],
+        scene_first_frames: &[usize],
+    ) -> Result<()> {
+        let len = metadata_list.len();
+        let frames = |range: Option<&str>, scenes: Option<&str>| {
+            self.target_frames(range, scenes, len, scene_first_frames)
+        };
+
+        if let Some(edits) = &self.profile_a {
+            for edit in edits {
+                let ranges = frames(edit.range.as_deref(), edit.scenes.as_deref())?;
+                let amount = edit_frames(metadata_list, ranges, |_, metadata| {
+                    convert_to_profile_a(metadata);
+                    Ok(())
+                })?;
+
+                println!("Converted {amount} frames to profile A.");
+            }
+        }
+
+        if let Some(edits) = &self.profile_b {
+            for edit in edits {
+                let ranges = frames(edit.range.as_deref(), edit.scenes.as_deref())?;
+                let target_luminance = Self::target_luminance(edit.target_luminance)?;
+                let bezier_curve =
+                    Self::bezier_curve(edit.knee_point_x, edit.knee_point_y, &edit.anchors)?;
+
+                let amount = edit_frames(metadata_list, ranges, |_, metadata| {
+                    metadata.tone_mapping_flag = true;
+                    metadata.targeted_system_display_maximum_luminance = target_luminance;
+                    metadata.bezier_curve = Some(bezier_curve.clone());
+
+                    Ok(())
+                })?;
+
+                println!("Converted {amount} frames to profile B.");
+            }
+        }
+
+        if let Some(edits) = &self.target_luminance {
+            for edit in edits {
+                let ranges = frames(edit.range.as_deref(), edit.scenes.as_deref())?;
+                let target_luminance = Self::target_luminance(edit.nits)?;
+
+                let amount = edit_frames(metadata_list, ranges, |frame, metadata| {
+                    ensure!(
+                        metadata.tone_mapping_flag,
+                        "frame {frame} is profile A, it must be converted with profile_b to set its target luminance"
+                    );
+
+                    metadata.targeted_system_display_maximum_luminance = target_luminance;
+
+                    Ok(())
+                })?;
+
+                println!(
+                    "Set target display luminance of {amount} frames to {target_luminance} nits."
+                );
+            }
+        }
+
+        if let Some(edits) = &self.bezier_curve {
+            for edit in edits {
+                let ranges = frames(edit.range.as_deref(), edit.scenes.as_deref())?;
+                let bezier_curve =
+                    Self::bezier_curve(edit.knee_point_x, edit.knee_point_y, &edit.anchors)?;
+
+                let amount = edit_frames(metadata_list, ranges, |frame, metadata| {
+                    ensure!(
+                        metadata.tone_mapping_flag,
+                        "frame {frame} is profile A, it must be converted with profile_b to set its Bezier curve"
+                    );
+
+                    metadata.bezier_curve = Some(bezier_curve.clone());
+
+                    Ok(())
+                })?;
+
+                println!("Replaced the Bezier curve of {amount} frames.");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Profile B target display, which can't be zero
+    fn target_luminance(nits: u32) -> Result<u32> {
+        ensure!(
+            (1..=10_000).contains(&nits),
+            "invalid target luminance {nits}, should be between 1 and 10000 nits"
+        );
 
-        if range.contains('-') {
-            let mut split = range.split('-');
+        Ok(nits)
+    }
+
+    fn bezier_curve(knee_point_x: u16, knee_point_y: u16, anchors: &[u16]) -> Result<BezierCurve> {
+        let num_bezier_curve_anchors = u8::try_from(anchors.len())
+            .with_context(|| format!("invalid number of Bezier curve anchors {}", anchors.len()))?;
 
-            if let Some(first) = split.next() {
-                if let Ok(first_num) = first.parse() {
-                    result.0 = first_num;
+        let bezier_curve = BezierCurve {
+            knee_point_x,
+            knee_point_y,
+            num_bezier_curve_anchors,
+            bezier_curve_anchors: anchors.to_vec(),
+        };
+        bezier_curve.validate()?;
+
+        Ok(bezier_curve)
+    }
+
+    /// Frames of an edit, from frame ranges or `SceneId`s. All the frames when both are `None`.
+    ///
+    /// Overlapping ranges are merged, so that frames are only edited once.
+    fn target_frames(
+        &self,
+        range: Option<&str>,
+        scenes: Option<&str>,
+        len: usize,
+        scene_first_frames: &[usize],
+    ) -> Result<Vec<RangeInclusive<usize>>> {
+        let mut ranges = match (range, scenes) {
+            (Some(_), Some(_)) => bail!("an edit can't have both a range and scenes"),
+            (None, Some(scenes)) => Self::scene_ranges(scenes, len, scene_first_frames)?,
+            (Some(range), None) => Self::list_ranges(range, len, self.fps)?,
+            (None, None) => {
+                ensure!(len > 0, "no metadata frames to edit");
+                vec![0..=len - 1]
+            }
+        };
+
+        ranges.sort_by_key(|range| *range.start());
+
+        let mut merged: Vec<RangeInclusive<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start() <= last.end() => {
+                    *last = *last.start()..=*range.end().max(last.end());
                 }
+                _ => merged.push(range),
             }
+        }
+
+        Ok(merged)
+    }
+
+    /// Frames of every scene of a `SceneId` list, like `1,4-6`, resolved from the input's scene information
+    fn scene_ranges(
+        scenes: &str,
+        len: usize,
+        scene_first_frames: &[usize],
+    ) -> Result<Vec<RangeInclusive<usize>>> {
+        let scene_count = scene_first_frames.len();
+        let mut ranges = Vec::new();
+
+        for scene_range in Self::list_ranges(scenes, usize::MAX, None)? {
+            ensure!(
+                *scene_range.end() < scene_count,
+                "invalid scene {}, the metadata has {scene_count} scenes",
+                scene_range.end()
+            );
+
+            for scene in scene_range {
+                let start = scene_first_frames[scene];
+                let end = scene_first_frames
+                    .get(scene + 1)
+                    .map_or(len, |next| *next)
+                    .saturating_sub(1);
+
+                ensure!(
+                    start <= end && end < len,
+                    "invalid scene {scene}, the scene information doesn't match the frames"
+                );
+
+                ranges.push(start..=end);
+            }
+        }
+
+        Ok(ranges)
+    }
+
+    /// Indexes or inclusive ranges separated by commas, like `0-39,50`.
+    ///
+    /// Timecodes and timestamps are converted to frames when the frame rate is set.
+    fn list_ranges(list: &str, len: usize, fps: Option<f64>) -> Result<Vec<RangeInclusive<usize>>> {
+        list.split(',')
+            .map(|range| {
+                let range = range.trim();
+
+                let (start, end) = if range.contains('-') {
+                    EditConfig::range_string_to_tuple(range, fps)?
+                } else {
+                    let index = parse_frame(range, fps)?;
 
-            if let Some(second) = split.next() {
-                if let Ok(second_num) = second.parse() {
-                    result.1 = second_num;
+                    (index, index)
+                };
+
+                ensure!(end < len, "invalid end range {}", end);
+                ensure!(start <= end, "invalid range {}, start is after end", range);
+
+                Ok(start..=end)
+            })
+            .collect()
+    }
+
+    /// Smooths every segment between the cuts on its own, so the filter doesn't cross scenes
+    fn smooth(
+        &self,
+        edit: &SmoothMetadata,
+        metadata_list: &mut [Option<Hdr10PlusMetadata>],
+        scene_first_frames: &[usize],
+    ) -> Result<()> {
+        edit.validate()?;
+
+        let ranges = self.target_frames(
+            edit.range.as_deref(),
+            edit.scenes.as_deref(),
+            metadata_list.len(),
+            scene_first_frames,
+        )?;
+
+        let average_pq = |metadata: &Option<Hdr10PlusMetadata>| {
+            metadata
+                .as_ref()
+                .map(|m| nits_to_pq(m.average_maxrgb as f64 / 10.0))
+        };
+
+        let mut amount = 0;
+        let mut cuts = 0;
+
+        for range in ranges {
+            let mut segment_starts = vec![*range.start()];
+
+            for frame in range.clone().skip(1) {
+                let scene_cut = edit.scene_cuts && scene_first_frames.binary_search(&frame).is_ok();
+                let real_cut = edit.cut_threshold.is_some_and(|threshold| {
+                    match (
+                        average_pq(&metadata_list[frame - 1]),
+                        average_pq(&metadata_list[frame]),
+                    ) {
+                        (Some(prev), Some(current)) => (current - prev).abs() > threshold,
+                        _ => false,
+                    }
+                });
+
+                if scene_cut || real_cut {
+                    segment_starts.push(frame);
                 }
             }
 
-            Ok(result)
-        } else {
-            bail!("Invalid edit range")
+            cuts += segment_starts.len() - 1;
+            segment_starts.push(range.end() + 1);
+
+            for segment in segment_starts.windows(2) {
+                let mut frames: Vec<&mut Hdr10PlusMetadata> = metadata_list[segment[0]..segment[1]]
+                    .iter_mut()
+                    .flatten()
+                    .collect();
+
+                smooth_frames(&mut frames, edit);
+                amount += frames.len();
+            }
         }
+
+        println!("Smoothed {amount} frames, reset at {cuts} cuts.");
+
+        Ok(())
     }
 
     fn remove_frames(
@@ -176,14 +904,16 @@ impl EditConfig {
 
         for range in ranges {
             if range.contains('-') {
-                let (start, end) = EditConfig::range_string_to_tuple(range)?;
+                let (start, end) = EditConfig::range_string_to_tuple(range, self.fps)?;
                 ensure!(end < metadata_list.len(), "invalid end range {}", end);
+                ensure!(start <= end, "invalid range {}, start is after end", range);
 
                 amount += end - start + 1;
                 metadata_list[start..=end]
                     .iter_mut()
                     .for_each(|e| *e = None);
-            } else if let Ok(index) = range.parse::<usize>() {
+            } else {
+                let index = parse_frame(range, self.fps)?;
                 ensure!(
                     index < metadata_list.len(),
                     "invalid frame index to remove {}",
@@ -201,6 +931,99 @@ impl EditConfig {
         Ok(())
     }
 
+    fn remove_scenes(
+        &self,
+        scenes: &[String],
+        metadata_list: &mut [Option<Hdr10PlusMetadata>],
+        scene_first_frames: &[usize],
+    ) -> Result<()> {
+        let len = metadata_list.len();
+        let mut amount = 0;
+
+        for scenes in scenes {
+            for range in Self::scene_ranges(scenes, len, scene_first_frames)? {
+                amount += range.clone().count();
+                metadata_list[range].iter_mut().for_each(|e| *e = None);
+            }
+        }
+
+        println!("Removed {amount} metadata frames of scenes.");
+
+        Ok(())
+    }
+
+    /// The frames are copied, so the scene cuts stay on a single frame
+    fn convert_frame_rate(
+        &self,
+        conversion: &FrameRateConversion,
+        list: Vec<Hdr10PlusMetadata>,
+    ) -> Result<Vec<Hdr10PlusMetadata>> {
+        let source = conversion.source.or(self.fps).context(
+            "the frame rate of the metadata is required to convert it, set with source, --fps or --input-hevc",
+        )?;
+        let target = conversion.target;
+
+        ensure!(!list.is_empty(), "no metadata frames to convert");
+
+        // Source frame of every output frame
+        let source_frames: Vec<usize> = match conversion.mode {
+            FrameRateMode::Resample => {
+                let len = (list.len() as f64 * target / source).round() as usize;
+
+                (0..len)
+                    .map(|frame| {
+                        timestamp_to_frame(frame as f64 / target, source).min(list.len() - 1)
+                    })
+                    .collect()
+            }
+            FrameRateMode::Pulldown if (target / source - 1.25).abs() < 0.001 => {
+                // The frames alternate between 2 and 3 fields, a last single field is dropped.
+                // Frames with the fields of two source frames use the second one, where a new scene starts
+                let fields: Vec<usize> = (0..list.len())
+                    .flat_map(|frame| std::iter::repeat_n(frame, 2 + frame % 2))
+                    .collect();
+
+                fields.chunks_exact(2).map(|frame| frame[1]).collect()
+            }
+            FrameRateMode::Pulldown if (target / source - 0.8).abs() < 0.001 => {
+                // Inverse telecine, the last frame of every 5 repeats the previous one
+                (0..list.len()).filter(|frame| frame % 5 != 4).collect()
+            }
+            FrameRateMode::Pulldown => bail!(
+                "invalid pulldown from {source} to {target} fps, it converts 4 frames to 5 like 23.976 to 29.97 fps, or back"
+            ),
+        };
+
+        let refs: Vec<&Hdr10PlusMetadata> = list.iter().collect();
+        let scene_info = compute_scene_frames(metadata_list_profile(&refs), &refs);
+
+        let scene_count = scene_info.last().map_or(0, |scene| scene.scene_id + 1);
+        let kept_scenes: HashSet<usize> = source_frames
+            .iter()
+            .map(|&frame| scene_info[frame].scene_id)
+            .collect();
+
+        if kept_scenes.len() < scene_count {
+            println!(
+                "Warning: {} scenes shorter than a frame at {target:.3} fps were dropped",
+                scene_count - kept_scenes.len()
+            );
+        }
+
+        println!(
+            "Converted {} frames at {:.3} fps to {} frames at {:.3} fps.",
+            list.len(),
+            source,
+            source_frames.len(),
+            target
+        );
+
+        Ok(source_frames
+            .into_iter()
+            .map(|frame| list[frame].clone())
+            .collect())
+    }
+
     fn duplicate_metadata(
         &self,
         to_duplicate: &[DuplicateMetadata],
@@ -212,17 +1035,17 @@ impl EditConfig {
         );
 
         for meta in to_duplicate {
+            let source = meta.source.frame(self.fps)?;
+            let offset = meta.offset.frame(self.fps)?;
+
             ensure!(
-                meta.source < metadata.len() && meta.offset <= metadata.len(),
+                source < metadata.len() && offset <= metadata.len(),
                 "invalid duplicate: {:?}",
                 meta
             );
 
-            let source = metadata[meta.source].clone();
-            metadata.splice(
-                meta.offset..meta.offset,
-                std::iter::repeat_n(source, meta.length),
-            );
+            let source = metadata[source].clone();
+            metadata.splice(offset..offset, std::iter::repeat_n(source, meta.length));
         }
 
         println!("Final metadata length: {}", metadata.len());
@@ -230,3 +1053,359 @@ impl EditConfig {
         Ok(())
     }
 }
+
+impl Default for FramePosition {
+    fn default() -> Self {
+        Self::Index(0)
+    }
+}
+
+impl FramePosition {
+    fn frame(&self, fps: Option<f64>) -> Result<usize> {
+        match self {
+            Self::Index(index) => Ok(*index),
+            Self::Time(value) => parse_frame(value, fps),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FrameRateValue {
+    Number(f64),
+    Fraction(String),
+}
+
+/// Frame rate as a number, or a string like `24000/1001`
+fn deserialize_frame_rate<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match FrameRateValue::deserialize(deserializer)? {
+        FrameRateValue::Number(fps) => parse_frame_rate(&fps.to_string()),
+        FrameRateValue::Fraction(fps) => parse_frame_rate(&fps),
+    }
+    .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_optional_frame_rate<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_frame_rate(deserializer).map(Some)
+}
+
+/// Frame index, SMPTE timecode, or timestamp in milliseconds (`1500ms`) or `HH:MM:SS.mmm`.
+///
+/// The timecodes and timestamps need the frame rate to be converted to frames.
+fn parse_frame(value: &str, fps: Option<f64>) -> Result<usize> {
+    let value = value.trim();
+
+    if !value.contains([':', ';']) && !value.ends_with("ms") {
+        return value
+            .parse()
+            .with_context(|| format!("invalid frame index {value}"));
+    }
+
+    let fps = fps.with_context(|| {
+        format!(
+            "converting {value} to a frame requires the frame rate, set with --fps or --input-hevc"
+        )
+    })?;
+
+    if let Some(ms) = value.strip_suffix("ms") {
+        let ms: f64 = ms
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid timestamp {value}"))?;
+        ensure!(ms.is_finite() && ms >= 0.0, "invalid timestamp {value}");
+
+        Ok(timestamp_to_frame(ms / 1000.0, fps))
+    } else if value.split([':', ';']).count() == 4 {
+        parse_timecode(value, fps)
+    } else {
+        parse_timestamp(value).map(|seconds| timestamp_to_frame(seconds, fps))
+    }
+}
+
+fn default_scene_cuts() -> bool {
+    true
+}
+
+impl SmoothMetadata {
+    fn validate(&self) -> Result<()> {
+        if let Some(window) = self.window {
+            ensure!(
+                window % 2 == 1,
+                "invalid smoothing window {window}, it must be odd"
+            );
+        }
+
+        if let Some(alpha) = self.alpha {
+            ensure!(
+                alpha > 0.0 && alpha <= 1.0,
+                "invalid smoothing alpha {alpha}, should be above 0 and at most 1"
+            );
+        }
+
+        if let Some(threshold) = self.cut_threshold {
+            ensure!(
+                threshold.is_finite() && threshold > 0.0,
+                "invalid cut threshold {threshold}"
+            );
+        }
+
+        Ok(())
+    }
+
+    fn filter(&self, values: &[f64]) -> Vec<f64> {
+        match self.method {
+            SmoothingMethod::Average | SmoothingMethod::Median => {
+                let radius = self.window.unwrap_or(DEFAULT_SMOOTHING_WINDOW) / 2;
+
+                // The windows are shorter at the edges, the values of other segments aren't used
+                (0..values.len())
+                    .map(|i| {
+                        let window =
+                            &values[i.saturating_sub(radius)..(i + radius + 1).min(values.len())];
+
+                        if self.method == SmoothingMethod::Average {
+                            window.iter().sum::<f64>() / window.len() as f64
+                        } else {
+                            median(window)
+                        }
+                    })
+                    .collect()
+            }
+            SmoothingMethod::Exponential => {
+                let alpha = self.alpha.unwrap_or(DEFAULT_SMOOTHING_ALPHA);
+                let mut smoothed = values.to_vec();
+
+                // Forward then backward, so the values don't lag behind
+                for i in 1..smoothed.len() {
+                    smoothed[i] = alpha * smoothed[i] + (1.0 - alpha) * smoothed[i - 1];
+                }
+                for i in (0..smoothed.len().saturating_sub(1)).rev() {
+                    smoothed[i] = alpha * smoothed[i] + (1.0 - alpha) * smoothed[i + 1];
+                }
+
+                smoothed
+            }
+        }
+    }
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+/// Luminance statistics are smoothed in PQ, the Bezier curve anchors as they are.
+///
+/// The distribution values are matched by percentage, excluding the 5% entry.
+fn smooth_frames(frames: &mut [&mut Hdr10PlusMetadata], edit: &SmoothMetadata) {
+    let to_pq = |value: u32| nits_to_pq(value as f64 / 10.0);
+    let from_pq = |pq: f64| {
+        (pq_to_nits(pq) * 10.0)
+            .round()
+            .clamp(0.0, MAX_LUMINANCE_VALUE as f64) as u32
+    };
+
+    for i in 0..3 {
+        smooth_values(
+            frames,
+            edit,
+            |m| Some(to_pq(m.maxscl[i])),
+            |m, pq| m.maxscl[i] = from_pq(pq),
+        );
+    }
+
+    smooth_values(
+        frames,
+        edit,
+        |m| Some(to_pq(m.average_maxrgb)),
+        |m, pq| m.average_maxrgb = from_pq(pq),
+    );
+
+    let percentages: BTreeSet<u8> = frames
+        .iter()
+        .flat_map(|m| m.distribution_maxrgb.iter().map(|d| d.percentage))
+        .filter(|&percentage| percentage != NON_LUMINANCE_PERCENTAGE)
+        .collect();
+
+    for percentage in percentages {
+        smooth_values(
+            frames,
+            edit,
+            |m| {
+                m.distribution_maxrgb
+                    .iter()
+                    .find(|d| d.percentage == percentage)
+                    .map(|d| to_pq(d.percentile))
+            },
+            |m, pq| {
+                if let Some(d) = m
+                    .distribution_maxrgb
+                    .iter_mut()
+                    .find(|d| d.percentage == percentage)
+                {
+                    d.percentile = from_pq(pq);
+                }
+            },
+        );
+    }
+
+    let anchor_count = frames
+        .iter()
+        .filter_map(|m| m.bezier_curve.as_ref())
+        .map(|bc| bc.bezier_curve_anchors.len())
+        .max()
+        .unwrap_or(0);
+
+    for i in 0..anchor_count {
+        smooth_values(
+            frames,
+            edit,
+            |m| {
+                m.bezier_curve
+                    .as_ref()
+                    .and_then(|bc| bc.bezier_curve_anchors.get(i))
+                    .map(|&anchor| anchor as f64)
+            },
+            |m, anchor| {
+                if let Some(value) = m
+                    .bezier_curve
+                    .as_mut()
+                    .and_then(|bc| bc.bezier_curve_anchors.get_mut(i))
+                {
+                    *value = anchor.round() as u16;
+                }
+            },
+        );
+    }
+}
+
+/// Filters the values of the frames that have one, in order
+fn smooth_values<G, S>(frames: &mut [&mut Hdr10PlusMetadata], edit: &SmoothMetadata, get: G, set: S)
+where
+    G: Fn(&Hdr10PlusMetadata) -> Option<f64>,
+    S: Fn(&mut Hdr10PlusMetadata, f64),
+{
+    let (indices, values): (Vec<usize>, Vec<f64>) = frames
+        .iter()
+        .enumerate()
+        .filter_map(|(i, m)| get(m).map(|value| (i, value)))
+        .unzip();
+
+    for (i, value) in indices.into_iter().zip(edit.filter(&values)) {
+        set(frames[i], value);
+    }
+}
+
+/// Maps the MaxScl, AverageMaxRGB and distribution percentile values of the frames, in nits.
+///
+/// The values are kept in the range allowed by the validation. Returns the number of frames edited.
+fn map_brightness<F>(
+    metadata_list: &mut [Option<Hdr10PlusMetadata>],
+    ranges: Vec<RangeInclusive<usize>>,
+    f: F,
+) -> Result<usize>
+where
+    F: Fn(f64) -> f64,
+{
+    let map_value = |value: u32| {
+        let nits = f(value as f64 / 10.0);
+        (nits * 10.0).round().clamp(0.0, MAX_LUMINANCE_VALUE as f64) as u32
+    };
+
+    edit_frames(metadata_list, ranges, |_, metadata| {
+        metadata.maxscl = metadata.maxscl.map(map_value);
+        metadata.average_maxrgb = map_value(metadata.average_maxrgb);
+
+        for distribution in metadata
+            .distribution_maxrgb
+            .iter_mut()
+            .filter(|d| d.percentage != NON_LUMINANCE_PERCENTAGE)
+        {
+            distribution.percentile = map_value(distribution.percentile);
+        }
+
+        Ok(())
+    })
+}
+
+/// Sets the luminance statistics of all the frames to their max or average.
+///
+/// The other values are the ones of the first frame, including the 5% distribution value.
+fn make_constant(frames: &mut [Option<Hdr10PlusMetadata>], mode: SceneStatistic) {
+    let Some(mut constant) = frames.iter().flatten().next().cloned() else {
+        return;
+    };
+
+    let combine = |values: Vec<u32>| match mode {
+        SceneStatistic::Max => values.into_iter().max().unwrap_or_default(),
+        SceneStatistic::Average => {
+            let sum: u64 = values.iter().map(|&v| v as u64).sum();
+            (sum as f64 / values.len().max(1) as f64).round() as u32
+        }
+    };
+
+    for (i, value) in constant.maxscl.iter_mut().enumerate() {
+        *value = combine(frames.iter().flatten().map(|m| m.maxscl[i]).collect());
+    }
+
+    constant.average_maxrgb = combine(frames.iter().flatten().map(|m| m.average_maxrgb).collect());
+
+    // Matched by percentage, the frames can have 9 or 10 percentiles
+    for distribution in constant
+        .distribution_maxrgb
+        .iter_mut()
+        .filter(|d| d.percentage != NON_LUMINANCE_PERCENTAGE)
+    {
+        distribution.percentile = combine(
+            frames
+                .iter()
+                .flatten()
+                .flat_map(|m| &m.distribution_maxrgb)
+                .filter(|d| d.percentage == distribution.percentage)
+                .map(|d| d.percentile)
+                .collect(),
+        );
+    }
+
+    for metadata in frames.iter_mut().flatten() {
+        metadata.clone_from(&constant);
+    }
+}
+
+/// Edits the frames of the ranges, and updates their profile. Returns the number of frames edited.
+fn edit_frames<F>(
+    metadata_list: &mut [Option<Hdr10PlusMetadata>],
+    ranges: Vec<RangeInclusive<usize>>,
+    mut f: F,
+) -> Result<usize>
+where
+    F: FnMut(usize, &mut Hdr10PlusMetadata) -> Result<()>,
+{
+    let mut amount = 0;
+
+    for range in ranges {
+        for (frame, metadata) in range.clone().zip(&mut metadata_list[range]) {
+            if let Some(metadata) = metadata {
+                f(frame, metadata)?;
+                metadata.set_profile();
+
+                amount += 1;
+            }
+        }
+    }
+
+    Ok(amount)
+}
@@ -14,6 +14,10 @@ impl Extractor {
             output,
             skip_reorder,
             limit,
+            report_layers,
+            duplicates,
+            lenient,
+            threads,
         } = args;
         let input = input_from_either("extract", input, input_pos)?;
 
@@ -23,6 +27,12 @@ impl Extractor {
             options.verify = true
         };
 
+        let threads = match threads {
+            Some(0) => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            Some(n) => n,
+            None => 1,
+        };
+
         let pb = initialize_progress_bar(&format, &input)?;
         let mut parser = Parser::new(
             input,
@@ -30,7 +40,14 @@ impl Extractor {
             options,
             pb,
             skip_reorder,
-            ParserOptions { limit },
+            ParserOptions {
+                limit,
+                report_layers,
+                duplicates,
+                lenient,
+                threads,
+                quiet: false,
+            },
         );
 
         parser.process_input(&format)
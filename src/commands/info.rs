@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use hdr10plus::metadata::{Hdr10PlusMetadata, PeakBrightnessSource, VariablePeakBrightness};
+
+use super::{ArgPeakBrightnessSource, CliOptions, InfoArgs, input_from_either};
+use crate::core::metadata_file::MetadataReader;
+
+pub struct Info {}
+
+/// Summary of the metadata of a whole file
+#[derive(Debug, Default, Serialize)]
+pub struct MetadataSummary {
+    pub profile: String,
+    pub application_versions: BTreeSet<u8>,
+
+    pub frame_count: usize,
+    pub scene_count: usize,
+    pub average_scene_length: f64,
+    pub max_scene_length: usize,
+
+    /// Highest frame average brightness, in nits
+    pub max_fall: f64,
+    /// Highest frame peak brightness in nits, by `--peak-source` name
+    pub max_cll: BTreeMap<&'static str, f64>,
+
+    pub targeted_system_display_maximum_luminance: BTreeSet<u32>,
+    /// `None` when no frame has a Bezier curve
+    pub knee_point: Option<KneePointRange>,
+    /// Number of entries in the `distribution_maxrgb` percentile sets
+    pub distribution_maxrgb_percentiles: BTreeSet<usize>,
+}
+
+/// Inclusive ranges of the Bezier curve knee points
+#[derive(Debug, Serialize)]
+pub struct KneePointRange {
+    pub x: [u16; 2],
+    pub y: [u16; 2],
+}
+
+impl Info {
+    pub fn info(args: InfoArgs, options: CliOptions) -> Result<()> {
+        let InfoArgs {
+            input,
+            input_pos,
+            json,
+        } = args;
+        let input = input_from_either("info", input, input_pos)?;
+
+        let mut reader = MetadataReader::from_source(&input, options)?;
+        let summary = MetadataSummary::from_reader(&mut reader)?;
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        } else {
+            summary.print();
+        }
+
+        Ok(())
+    }
+}
+
+impl MetadataSummary {
+    pub fn from_reader(reader: &mut MetadataReader) -> Result<Self> {
+        let mut summary = Self::default();
+
+        while let Some(metadata) = reader.next_frame()? {
+            summary.add_frame(&metadata);
+        }
+
+        summary.frame_count = reader.frame_count();
+        summary.profile = reader.profile().unwrap_or("N/A").to_string();
+
+        let mut scene_first_frames = reader.scene_first_frames().unwrap_or_default();
+        summary.scene_count = scene_first_frames.len();
+
+        if summary.scene_count > 0 {
+            scene_first_frames.push(summary.frame_count);
+
+            summary.average_scene_length = summary.frame_count as f64 / summary.scene_count as f64;
+            summary.max_scene_length = scene_first_frames
+                .windows(2)
+                .map(|w| w[1].saturating_sub(w[0]))
+                .max()
+                .unwrap_or_default();
+        }
+
+        Ok(summary)
+    }
+
+    fn add_frame(&mut self, metadata: &Hdr10PlusMetadata) {
+        self.application_versions
+            .insert(metadata.application_version);
+
+        self.max_fall = self.max_fall.max(metadata.average_maxrgb as f64 / 10.0);
+
+        for source in ArgPeakBrightnessSource::value_variants() {
+            let peak = metadata.peak_brightness_nits(PeakBrightnessSource::from(*source));
+
+            if let Some(peak) = peak {
+                let max_cll = self.max_cll.entry(source_name(*source)).or_default();
+                *max_cll = max_cll.max(peak);
+            }
+        }
+
+        self.targeted_system_display_maximum_luminance
+            .insert(metadata.targeted_system_display_maximum_luminance);
+
+        if let Some(bc) = &metadata.bezier_curve {
+            let (x, y) = (bc.knee_point_x, bc.knee_point_y);
+
+            match &mut self.knee_point {
+                Some(range) => {
+                    range.x = [range.x[0].min(x), range.x[1].max(x)];
+                    range.y = [range.y[0].min(y), range.y[1].max(y)];
+                }
+                None => {
+                    self.knee_point = Some(KneePointRange {
+                        x: [x, x],
+                        y: [y, y],
+                    })
+                }
+            }
+        }
+
+        self.distribution_maxrgb_percentiles
+            .insert(metadata.distribution_maxrgb.len());
+    }
+
+    fn print(&self) {
+        println!("Profile: {}", self.profile);
+        println!(
+            "Application version: {}",
+            join_values(&self.application_versions)
+        );
+        println!("Frames: {}", self.frame_count);
+        println!("Scenes: {}", self.scene_count);
+        println!("  Average length: {:.2} frames", self.average_scene_length);
+        println!("  Max length: {} frames", self.max_scene_length);
+
+        println!("MaxFALL: {:.2} nits", self.max_fall);
+        println!("MaxCLL by peak source:");
+        for (source, max_cll) in &self.max_cll {
+            println!("  {source}: {max_cll:.2} nits");
+        }
+
+        println!(
+            "Targeted system display maximum luminance: {}",
+            join_values(&self.targeted_system_display_maximum_luminance)
+        );
+
+        match &self.knee_point {
+            Some(range) => println!(
+                "Knee point: x {}-{}, y {}-{}",
+                range.x[0], range.x[1], range.y[0], range.y[1]
+            ),
+            None => println!("Knee point: none, no Bezier curve"),
+        }
+
+        println!(
+            "Distribution percentiles: {} entries",
+            join_values(&self.distribution_maxrgb_percentiles)
+        );
+    }
+}
+
+/// Same as the `--peak-source` values
+fn source_name(source: ArgPeakBrightnessSource) -> &'static str {
+    match source {
+        ArgPeakBrightnessSource::Histogram => "histogram",
+        ArgPeakBrightnessSource::Histogram99 => "histogram99",
+        ArgPeakBrightnessSource::MaxScl => "max-scl",
+        ArgPeakBrightnessSource::MaxSclLuminance => "max-scl-luminance",
+    }
+}
+
+fn join_values<T: ToString>(values: &BTreeSet<T>) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    values.join(", ")
+}
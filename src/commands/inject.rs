@@ -1,40 +1,70 @@
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write, stdout};
-use std::path::PathBuf;
+use std::io::{BufReader, Write, stdout};
+use std::path::{Path, PathBuf};
 
-use anyhow::{Result, bail};
-use hevc_parser::utils::{
-    add_start_code_emulation_prevention_3_byte, clear_start_code_emulation_prevention_3_byte,
-};
+use anyhow::{Context, Result, bail, ensure};
+use hevc_parser::utils::clear_start_code_emulation_prevention_3_byte;
 use indicatif::ProgressBar;
 
 use hevc_parser::io::{FrameBuffer, IoFormat, IoProcessor, NalBuffer, processor};
 use hevc_parser::{HevcParser, NALUStartCode, hevc::*};
 use processor::{HevcProcessor, HevcProcessorOpts};
 
-use hdr10plus::metadata_json::{Hdr10PlusJsonMetadata, MetadataJsonRoot};
+use hdr10plus::hevc::{NalHeaderIds, append_hdr10plus_sei_message, encode_hdr10plus_nal_with_ids};
+use hdr10plus::metadata::{Hdr10PlusMetadata, Hdr10PlusMetadataEncOpts};
 
 use crate::commands::InjectArgs;
-use crate::core::{initialize_progress_bar, st2094_40_sei_msg};
+use crate::core::metadata_file::MetadataReader;
+use crate::core::verifier::OutputVerifier;
+use crate::core::{
+    ChangeReport, CountingReader, OutputWriter, PresentationIndex, StartCodeTracker,
+    initialize_progress_bar, is_sei_nal_type, sei_removed_hdr10plus_nalu, st2094_40_sei_msg,
+};
 
 use super::{CliOptions, input_from_either};
 
 pub struct Injector {
     input: PathBuf,
     json_in: PathBuf,
+    output: PathBuf,
     options: CliOptions,
+    verify_output: bool,
+    dry_run: bool,
+    preserve_start_codes: bool,
+    merge_prefix_sei: bool,
 
-    metadata_list: Vec<Hdr10PlusJsonMetadata>,
+    metadata: MetadataWindow,
 
-    writer: BufWriter<File>,
+    writer: OutputWriter,
     progress_bar: ProgressBar,
     already_checked_for_hdr10plus: bool,
 
-    frames: Vec<Frame>,
+    /// Parsed frames from the first pass
+    frames: PresentationIndex,
     nals: Vec<NALUnit>,
-    mismatched_length: bool,
 
     frame_buffer: FrameBuffer,
+    start_code_tracker: StartCodeTracker,
+    report: ChangeReport,
+}
+
+/// Metadata frames of the JSON or binary file, read as the presentation numbers are requested.
+///
+/// Frames are released once used, so only the reorder window is kept in memory.
+/// The file is read once, the frame count is only known after its last frame was read.
+/// The last released frame is kept, to be duplicated when the video is longer.
+struct MetadataWindow {
+    reader: MetadataReader,
+
+    /// Frames read and not released yet, with whether they were used
+    frames: VecDeque<(Hdr10PlusMetadata, bool)>,
+    /// Presentation number of the first frame in `frames`
+    first: usize,
+    /// Whether the reader returned its last frame
+    ended: bool,
+    /// Last released frame, duplicated for the frames past the end
+    last_released: Option<Hdr10PlusMetadata>,
 }
 
 impl Injector {
@@ -44,6 +74,10 @@ impl Injector {
             input_pos,
             json,
             output,
+            verify_output,
+            preserve_start_codes,
+            merge_prefix_sei,
+            dry_run,
         } = args;
 
         let input = input_from_either("inject", input, input_pos)?;
@@ -56,36 +90,43 @@ impl Injector {
         let chunk_size = 100_000;
         let progress_bar = initialize_progress_bar(&IoFormat::Raw, &input)?;
 
-        let writer =
-            BufWriter::with_capacity(chunk_size, File::create(output).expect("Can't create file"));
+        let writer = if dry_run {
+            OutputWriter::dry_run()
+        } else {
+            OutputWriter::new(&output, chunk_size)?
+        };
+
+        println!("Parsing JSON file...");
+        stdout().flush().ok();
 
         let mut injector = Injector {
             input,
-            json_in: json,
+            json_in: json.clone(),
+            output,
             options: cli_options,
-            metadata_list: Vec::new(),
+            verify_output,
+            dry_run,
+            preserve_start_codes,
+            merge_prefix_sei,
+            metadata: MetadataWindow::new(&json)?,
 
             writer,
             progress_bar,
             already_checked_for_hdr10plus: false,
 
-            frames: Vec::new(),
+            frames: PresentationIndex::default(),
             nals: Vec::new(),
-            mismatched_length: false,
 
             frame_buffer: FrameBuffer {
                 frame_number: 0,
                 nals: Vec::with_capacity(16),
             },
+            start_code_tracker: StartCodeTracker::default(),
+            report: ChangeReport::default(),
         };
 
-        println!("Parsing JSON file...");
-        stdout().flush().ok();
-
-        let metadata_root = MetadataJsonRoot::from_file(&injector.json_in)?;
-        injector.metadata_list = metadata_root.scene_info;
-
-        if injector.metadata_list.is_empty() {
+        // Only the first frame is read before processing the video
+        if injector.metadata.get(0)?.is_none() {
             bail!("Empty HDR10+ SceneInfo array");
         }
 
@@ -100,7 +141,13 @@ impl Injector {
             let mut injector = Injector::from_args(args, cli_options)?;
 
             injector.process_input()?;
-            injector.interleave_hdr10plus_nals()
+            injector.interleave_hdr10plus_nals()?;
+
+            if injector.verify_output {
+                injector.verify_output()?;
+            }
+
+            Ok(())
         } else {
             bail!("Injector: Must be a raw HEVC bitstream file")
         }
@@ -122,26 +169,11 @@ impl Injector {
     }
 
     fn interleave_hdr10plus_nals(&mut self) -> Result<()> {
-        let metadata_list = &self.metadata_list;
-        self.mismatched_length = if self.frames.len() != metadata_list.len() {
-            println!(
-                "\nWarning: mismatched lengths. video {}, HDR10+ JSON {}",
-                self.frames.len(),
-                metadata_list.len()
-            );
-
-            if metadata_list.len() < self.frames.len() {
-                println!("Metadata will be duplicated at the end to match video length\n");
-            } else {
-                println!("Metadata will be skipped at the end to match video length\n");
-            }
-
-            true
+        if self.dry_run {
+            println!("Simulating interleaving of HDR10+ SEI NALs..");
         } else {
-            false
-        };
-
-        println!("Rewriting file with interleaved HDR10+ SEI NALs..");
+            println!("Rewriting file with interleaved HDR10+ SEI NALs..");
+        }
         stdout().flush().ok();
 
         self.progress_bar = initialize_progress_bar(&IoFormat::Raw, &self.input)?;
@@ -152,65 +184,163 @@ impl Injector {
             HevcProcessor::new(IoFormat::Raw, HevcProcessorOpts::default(), chunk_size);
 
         let file = File::open(&self.input)?;
-        let mut reader = Box::new(BufReader::with_capacity(chunk_size, file));
+        let mut reader = CountingReader::new(BufReader::with_capacity(chunk_size, file));
 
-        processor.process_io(&mut reader, self)
+        processor.process_io(&mut reader, self)?;
+
+        if self.dry_run {
+            self.report.input_size = reader.read_bytes();
+            self.report.output_size = self.writer.written_bytes();
+
+            self.report.print();
+        }
+
+        Ok(())
+    }
+
+    /// Inserts the HDR10+ SEI into the buffered frame, and writes its NALUs
+    fn write_buffered_frame(&mut self) -> Result<()> {
+        let insertion = Self::get_metadata_and_index_to_insert(
+            &self.frames,
+            &mut self.metadata,
+            &self.frame_buffer,
+        )?;
+
+        let Some((idx, metadata)) = insertion else {
+            // Nothing to attach metadata to, keep the NALUs as they are
+            return self.write_frame_buffer_nals();
+        };
+
+        let validate = self.options.validate;
+
+        // Last base layer prefix SEI NALU before the first slice
+        let existing_sei_nb = self
+            .merge_prefix_sei
+            .then(|| {
+                self.frame_buffer.nals[..idx]
+                    .iter_mut()
+                    .rev()
+                    .find(|nb| nb.nal_type == NAL_SEI_PREFIX && is_base_layer(nb))
+            })
+            .flatten();
+
+        if let Some(sei_nb) = existing_sei_nb {
+            sei_nb.data = append_hdr10plus_sei_message(&sei_nb.data, &metadata, validate)?;
+            self.report.nals_rewritten += 1;
+        } else {
+            let first_slice = &self.frame_buffer.nals[idx];
+
+            let start_code = if self.preserve_start_codes {
+                // Same as the slice it is inserted before
+                first_slice.start_code
+            } else {
+                NALUStartCode::Length4
+            };
+
+            // The SEI must have the same TemporalId as the access unit
+            let ids = NalHeaderIds::from_nal_header(&first_slice.data);
+
+            let hdr10plus_nb = NalBuffer {
+                nal_type: NAL_SEI_PREFIX,
+                start_code,
+                data: encode_hdr10plus_nal_with_ids(&metadata, validate, ids)?,
+            };
+
+            self.frame_buffer.nals.insert(idx, hdr10plus_nb);
+            self.report.nals_added += 1;
+        }
+
+        self.write_frame_buffer_nals()
+    }
+
+    fn write_frame_buffer_nals(&mut self) -> Result<()> {
+        for nal_buf in &self.frame_buffer.nals {
+            let start_code = if self.preserve_start_codes {
+                nal_buf.start_code
+            } else {
+                NALUStartCode::Length4
+            };
+
+            self.writer.write_all(start_code.slice())?;
+            self.writer.write_all(&nal_buf.data)?;
+        }
+
+        self.frame_buffer.nals.clear();
+
+        Ok(())
+    }
+
+    fn verify_output(&self) -> Result<()> {
+        let opts = Hdr10PlusMetadataEncOpts {
+            validate: self.options.validate,
+            ..Default::default()
+        };
+
+        // Same selection as the injection: metadata is duplicated at the end if too short.
+        // Read again from the file, as the frames are compared.
+        let mut reader = MetadataReader::from_file(&self.json_in)?;
+        let mut last_payload = None;
+
+        let expected = (0..self.frames.len()).map(move |_| {
+            if let Some(meta) = reader.next_frame()? {
+                last_payload = Some(meta.encode_with_opts(&opts)?);
+            }
+
+            last_payload.clone().context("Empty metadata list")
+        });
+
+        let verifier = OutputVerifier::new(
+            self.output.clone(),
+            Box::new(expected),
+            self.options.validate,
+        )?;
+        verifier.verify()
     }
 
     fn get_metadata_and_index_to_insert(
-        frames: &[Frame],
-        metadata_list: &[Hdr10PlusJsonMetadata],
+        frames: &PresentationIndex,
+        metadata: &mut MetadataWindow,
         frame_buffer: &FrameBuffer,
-        mismatched_length: bool,
-        validate: bool,
-    ) -> Result<(usize, NalBuffer)> {
-        let existing_frame = frames
+    ) -> Result<Option<(usize, Hdr10PlusMetadata)>> {
+        let presentation_number = frames.presentation_number(frame_buffer.frame_number);
+
+        // First base layer slice.
+        // Enhancement layer and Dolby Vision NALUs are kept in place, after it
+        let insert_index = frame_buffer
+            .nals
             .iter()
-            .find(|f| f.decoded_number == frame_buffer.frame_number);
+            .position(|nb| NALUnit::is_type_slice(nb.nal_type) && is_base_layer(nb));
+
+        // Not a parsed frame, and no base layer picture: only non-VCL or enhancement layer NALUs
+        if presentation_number.is_none() && insert_index.is_none() {
+            return Ok(None);
+        }
 
         // If we have a metadata buffered frame, write it
         // Otherwise, write the same data as previous
-        let hdr10plus_nb = if let Some(frame) = existing_frame {
-            let meta = metadata_list
-                .get(frame.presentation_number as usize)
-                .or_else(|| mismatched_length.then(|| metadata_list.last()).flatten());
-
-            if let Some(meta) = meta {
-                let data = hdr10plus::hevc::encode_hevc_from_json(meta, validate)?;
-
-                Some(NalBuffer {
-                    nal_type: NAL_SEI_PREFIX,
-                    start_code: NALUStartCode::Length4,
-                    data,
-                })
-            } else {
-                bail!(
-                    "No metadata found for presentation frame {}",
-                    frame.presentation_number
-                );
+        let meta = if let Some(presentation_number) = presentation_number {
+            // Past the end of the JSON, the last metadata is duplicated
+            let meta = match metadata.take(presentation_number as usize)? {
+                Some(meta) => Some(meta),
+                None => metadata.last(),
+            };
+
+            if meta.is_none() {
+                bail!("No metadata found for presentation frame {presentation_number}");
             }
-        } else if mismatched_length && let Some(meta) = metadata_list.last() {
-            let data = hdr10plus::hevc::encode_hevc_from_json(meta, validate)?;
 
-            Some(NalBuffer {
-                nal_type: NAL_UNSPEC62,
-                start_code: NALUStartCode::Length4,
-                data,
-            })
+            meta
+        } else if metadata.len()? != frames.len() {
+            // Frame missing from the parsed list, e.g. incomplete at the end of the stream
+            metadata.last()
         } else {
             None
         };
 
-        if let Some(hdr10plus_nb) = hdr10plus_nb {
-            // First slice
-            let insert_index = frame_buffer
-                .nals
-                .iter()
-                .position(|nb| NALUnit::is_type_slice(nb.nal_type));
-
+        if let Some(meta) = meta {
             if let Some(idx) = insert_index {
                 // we want the SEI before the slice
-                Ok((idx, hdr10plus_nb))
+                Ok(Some((idx, meta)))
             } else {
                 bail!(
                     "No slice in decoded frame {}. Cannot insert HDR10+ SEI.",
@@ -242,69 +372,56 @@ impl IoProcessor for Injector {
     fn process_nals(&mut self, _parser: &HevcParser, nals: &[NALUnit], chunk: &[u8]) -> Result<()> {
         // Second pass
         if !self.frames.is_empty() && !self.nals.is_empty() {
-            let metadata_list = &self.metadata_list;
-
             for nal in nals {
                 if self.frame_buffer.frame_number != nal.decoded_frame_index {
-                    let (idx, hdr10plus_nb) = Self::get_metadata_and_index_to_insert(
-                        &self.frames,
-                        metadata_list,
-                        &self.frame_buffer,
-                        self.mismatched_length,
-                        self.options.validate,
-                    )?;
-
-                    self.frame_buffer.nals.insert(idx, hdr10plus_nb);
-
-                    // Write NALUs for the frame
-                    for nal_buf in &self.frame_buffer.nals {
-                        self.writer.write_all(NALUStartCode::Length4.slice())?;
-                        self.writer.write_all(&nal_buf.data)?;
-                    }
-
+                    self.write_buffered_frame()?;
                     self.frame_buffer.frame_number = nal.decoded_frame_index;
-                    self.frame_buffer.nals.clear();
                 }
 
-                let (st2094_40_msg, payload) = if nal.nal_type == NAL_SEI_PREFIX {
-                    let sei_payload =
-                        clear_start_code_emulation_prevention_3_byte(&chunk[nal.start..nal.end]);
-                    let msg = st2094_40_sei_msg(&sei_payload, self.options.validate)?;
+                let start_code = self.start_code_tracker.start_code(nal);
+
+                // Existing HDR10+ is only replaced in the base layer, in prefix or suffix SEIs
+                let is_base_layer_sei = is_sei_nal_type(nal.nal_type) && nal.nuh_layer_id == 0;
 
-                    (msg, Some(sei_payload))
+                let (st2094_40_count, payload) = if is_base_layer_sei {
+                    sei_removed_hdr10plus_nalu(chunk, nal, self.options.validate)?
                 } else {
-                    (None, None)
+                    (0, None)
                 };
 
-                if let (Some(msg), Some(mut payload)) = (st2094_40_msg, payload) {
-                    let messages = SeiMessage::parse_sei_rbsp(&payload)?;
-
-                    // Only remove ST2094-40 message if there are others
-                    if messages.len() > 1 {
-                        let start = msg.msg_offset;
-                        let end = msg.payload_offset + msg.payload_size;
-
-                        payload.drain(start..end);
-                        add_start_code_emulation_prevention_3_byte(&mut payload);
+                if st2094_40_count > 0 {
+                    if nal.nal_type == NAL_SEI_SUFFIX {
+                        self.report.suffix_hdr10plus_seis += st2094_40_count;
+                    }
 
+                    // Other SEI messages are kept
+                    if let Some(payload) = payload {
                         self.frame_buffer.nals.push(NalBuffer {
                             nal_type: nal.nal_type,
-                            start_code: nal.start_code,
+                            start_code,
                             data: payload,
                         });
+
+                        self.report.shared_hdr10plus_seis += st2094_40_count;
+                        self.report.nals_rewritten += 1;
+                    } else {
+                        self.report.standalone_hdr10plus_seis += st2094_40_count;
+                        self.report.nals_dropped += 1;
                     }
                 } else {
                     self.frame_buffer.nals.push(NalBuffer {
                         nal_type: nal.nal_type,
-                        start_code: nal.start_code,
+                        start_code,
                         data: chunk[nal.start..nal.end].to_vec(),
                     });
                 }
             }
+
+            self.start_code_tracker.finish_chunk(nals, chunk);
         } else if !self.already_checked_for_hdr10plus {
             let existing_hdr10plus = nals
                 .iter()
-                .filter(|nal| nal.nal_type == NAL_SEI_PREFIX)
+                .filter(|nal| is_sei_nal_type(nal.nal_type) && nal.nuh_layer_id == 0)
                 .any(|nal| {
                     let sei_payload =
                         clear_start_code_emulation_prevention_3_byte(&chunk[nal.start..nal.end]);
@@ -324,9 +441,11 @@ impl IoProcessor for Injector {
     }
 
     fn finalize(&mut self, parser: &HevcParser) -> Result<()> {
+        self.progress_bar.finish_and_clear();
+
         // First pass
         if self.frames.is_empty() && self.nals.is_empty() {
-            self.frames.clone_from(parser.ordered_frames());
+            self.frames = PresentationIndex::new(parser.ordered_frames());
             self.nals.clone_from(parser.get_nals());
         } else {
             let ordered_frames = parser.ordered_frames();
@@ -336,33 +455,115 @@ impl IoProcessor for Injector {
             if (self.frame_buffer.frame_number as usize) != total_frames
                 && !self.frame_buffer.nals.is_empty()
             {
-                let metadata_list = &self.metadata_list;
-
-                let (idx, hdr10plus_nb) = Self::get_metadata_and_index_to_insert(
-                    &self.frames,
-                    metadata_list,
-                    &self.frame_buffer,
-                    self.mismatched_length,
-                    self.options.validate,
-                )?;
-
-                self.frame_buffer.nals.insert(idx, hdr10plus_nb);
-
-                // Write NALUs for the last frame
-                for nal_buf in &self.frame_buffer.nals {
-                    self.writer.write_all(NALUStartCode::Length4.slice())?;
-                    self.writer.write_all(&nal_buf.data)?;
-                }
-
-                self.frame_buffer.nals.clear();
+                self.write_buffered_frame()?;
             }
 
             // Second pass
             self.writer.flush()?;
-        }
 
-        self.progress_bar.finish_and_clear();
+            self.report.access_units = total_frames;
+
+            // Only known once the metadata was read up to the end
+            let metadata_len = self.metadata.len()?;
+
+            if self.frames.len() != metadata_len {
+                println!(
+                    "\nWarning: mismatched lengths. video {}, HDR10+ JSON {}",
+                    self.frames.len(),
+                    metadata_len
+                );
+
+                if metadata_len < self.frames.len() {
+                    println!("Metadata was duplicated at the end to match video length\n");
+                } else {
+                    println!("Metadata was skipped at the end to match video length\n");
+                }
+            }
+        }
 
         Ok(())
     }
 }
+
+impl MetadataWindow {
+    fn new(json: &Path) -> Result<Self> {
+        let reader = MetadataReader::from_file(json)
+            .with_context(|| format!("Failed reading metadata file {}", json.display()))?;
+
+        Ok(Self {
+            reader,
+            frames: VecDeque::new(),
+            first: 0,
+            ended: false,
+            last_released: None,
+        })
+    }
+
+    /// Reads the next frame into the window, returns false at the end of the file
+    fn read_frame(&mut self) -> Result<bool> {
+        if !self.ended {
+            match self.reader.next_frame()? {
+                Some(meta) => self.frames.push_back((meta, false)),
+                None => self.ended = true,
+            }
+        }
+
+        Ok(!self.ended)
+    }
+
+    fn get(&mut self, presentation_number: usize) -> Result<Option<&Hdr10PlusMetadata>> {
+        ensure!(
+            presentation_number >= self.first,
+            "Metadata for presentation frame {presentation_number} was already used"
+        );
+
+        while self.first + self.frames.len() <= presentation_number && self.read_frame()? {}
+
+        let frame = self.frames.get(presentation_number - self.first);
+
+        Ok(frame.map(|(meta, _)| meta))
+    }
+
+    /// Returns the frame's metadata, and releases the frames that were all used
+    fn take(&mut self, presentation_number: usize) -> Result<Option<Hdr10PlusMetadata>> {
+        if self.get(presentation_number)?.is_none() {
+            return Ok(None);
+        }
+
+        let (meta, used) = &mut self.frames[presentation_number - self.first];
+        let metadata = meta.clone();
+        *used = true;
+
+        while self.frames.front().is_some_and(|(_, used)| *used) {
+            self.last_released = self.frames.pop_front().map(|(meta, _)| meta);
+            self.first += 1;
+        }
+
+        Ok(Some(metadata))
+    }
+
+    /// Last frame of the file, only once it was read.
+    /// Called when the window runs out, so the frames past the end.
+    fn last(&self) -> Option<Hdr10PlusMetadata> {
+        if !self.ended {
+            return None;
+        }
+
+        self.frames
+            .back()
+            .map(|(meta, _)| meta)
+            .or(self.last_released.as_ref())
+            .cloned()
+    }
+
+    /// Total number of frames, the remaining frames are read into the window
+    fn len(&mut self) -> Result<usize> {
+        while self.read_frame()? {}
+
+        Ok(self.reader.frame_count())
+    }
+}
+
+fn is_base_layer(nal_buf: &NalBuffer) -> bool {
+    NalHeaderIds::from_nal_header(&nal_buf.data).is_some_and(|ids| ids.nuh_layer_id == 0)
+}
@@ -0,0 +1,234 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufWriter, Write, stdout};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail, ensure};
+
+use hdr10plus::metadata::Hdr10PlusMetadata;
+use hdr10plus::metadata_json::MetadataJsonWriter;
+
+use super::{MergeArgs, parse_frame_range};
+use crate::core::metadata_file::{MetadataReader, convert_to_profile_a};
+use crate::core::parser::{TOOL_NAME, TOOL_VERSION};
+
+pub struct Merger {
+    inputs: Vec<MergeInput>,
+    output: PathBuf,
+    mixed_profiles: MixedProfiles,
+}
+
+/// Metadata file to merge, with the range of frames to keep
+#[derive(Debug, Clone)]
+pub struct MergeInput {
+    pub path: PathBuf,
+    /// Inclusive, all the frames when `None`
+    pub range: Option<(usize, usize)>,
+}
+
+/// How to merge inputs with both profile A and profile B metadata
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MixedProfiles {
+    /// Fail the merge
+    #[default]
+    Error,
+    /// Convert the profile B frames to profile A, removing their Bezier curve
+    ProfileA,
+    /// Keep the frames as they are, the merged profile is N/A
+    Keep,
+}
+
+/// Frames, profiles and version of an input, in its frame range
+struct InputSummary {
+    frame_count: usize,
+    /// Profiles of the frames, before converting them
+    profiles: BTreeSet<String>,
+    version: Option<u8>,
+}
+
+impl Merger {
+    pub fn merge(args: MergeArgs) -> Result<()> {
+        let MergeArgs {
+            inputs,
+            output,
+            mixed_profiles,
+        } = args;
+
+        let merger = Merger {
+            inputs,
+            output,
+            mixed_profiles,
+        };
+
+        println!("Reading metadata files...");
+        let summaries = merger
+            .inputs
+            .iter()
+            .map(|input| merger.summarize_input(input))
+            .collect::<Result<Vec<_>>>()?;
+
+        // The frames are checked, an input can already have both profiles
+        let profiles: BTreeSet<&str> = summaries
+            .iter()
+            .flat_map(|s| s.profiles.iter().map(String::as_str))
+            .collect();
+
+        if profiles.contains("A")
+            && profiles.contains("B")
+            && merger.mixed_profiles == MixedProfiles::Error
+        {
+            bail!(
+                "Inputs have both profile A and profile B metadata, use --mixed-profiles to merge them"
+            );
+        }
+
+        let version = summaries.iter().find_map(|s| s.version).unwrap_or(1);
+
+        merger.write_json(merger.merged_profile(profiles), version)
+    }
+
+    fn summarize_input(&self, input: &MergeInput) -> Result<InputSummary> {
+        let mut reader = MetadataReader::from_file(&input.path)?;
+        let mut profiles = BTreeSet::new();
+        let mut version = None;
+
+        while let Some(metadata) = reader.next_frame()? {
+            let frame = reader.frame_count() - 1;
+
+            if input.contains(frame) {
+                version = version.or(Some(metadata.application_version));
+                profiles.insert(metadata.profile);
+            }
+        }
+
+        let total_frames = reader.frame_count();
+        let (start, end) = input.range.unwrap_or((0, total_frames.saturating_sub(1)));
+
+        ensure!(
+            end < total_frames,
+            "Invalid range {start}-{end} for {}, it has {total_frames} frames",
+            input.path.display()
+        );
+
+        let summary = InputSummary {
+            frame_count: end - start + 1,
+            profiles,
+            version,
+        };
+
+        println!(
+            "  {}: frames {start}-{end} ({} frames), profile {}",
+            input.path.display(),
+            summary.frame_count,
+            list_profile(summary.profiles.iter().map(String::as_str))
+        );
+
+        Ok(summary)
+    }
+
+    /// Converts the frame according to the mixed profiles policy
+    fn reconcile(&self, metadata: &mut Hdr10PlusMetadata) {
+        if self.mixed_profiles == MixedProfiles::ProfileA && metadata.profile == "B" {
+            convert_to_profile_a(metadata);
+        }
+    }
+
+    /// Profile of the merged frames, after converting them
+    fn merged_profile<'a>(&self, profiles: BTreeSet<&'a str>) -> &'a str {
+        let profiles: BTreeSet<&str> = profiles
+            .into_iter()
+            .map(|profile| {
+                if self.mixed_profiles == MixedProfiles::ProfileA && profile == "B" {
+                    "A"
+                } else {
+                    profile
+                }
+            })
+            .collect();
+
+        list_profile(profiles.into_iter())
+    }
+
+    fn for_each_frame<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(Hdr10PlusMetadata) -> Result<()>,
+    {
+        for input in &self.inputs {
+            let mut reader = MetadataReader::from_file(&input.path)?;
+
+            while let Some(mut metadata) = reader.next_frame()? {
+                let frame = reader.frame_count() - 1;
+
+                if input.range.is_some_and(|(_, end)| frame > end) {
+                    break;
+                }
+
+                if input.contains(frame) {
+                    self.reconcile(&mut metadata);
+                    f(metadata)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_json(&self, profile: &str, version: u8) -> Result<()> {
+        let save_file = File::create(&self.output)
+            .with_context(|| format!("Failed creating output file {}", self.output.display()))?;
+        let writer = BufWriter::with_capacity(10_000_000, save_file);
+
+        print!("Generating and writing metadata to JSON file... ");
+        stdout().flush().ok();
+
+        // The scenes are recomputed across the inputs
+        let mut json_writer = MetadataJsonWriter::new(writer, profile, version)?;
+        self.for_each_frame(|metadata| json_writer.write_frame(metadata))?;
+
+        let mut writer = json_writer.finish(TOOL_NAME, TOOL_VERSION)?;
+        writeln!(writer)?;
+        writer.flush()?;
+
+        println!("Done.");
+
+        Ok(())
+    }
+}
+
+/// The profile shared by all the frames, `N/A` when they are different
+fn list_profile<'a>(mut profiles: impl Iterator<Item = &'a str>) -> &'a str {
+    match (profiles.next(), profiles.next()) {
+        (Some(profile), None) => profile,
+        _ => "N/A",
+    }
+}
+
+impl MergeInput {
+    /// `path` or `path:start-end`, the range is inclusive
+    pub fn parse(value: &str) -> Result<Self> {
+        let suffix = value.rsplit_once(':').filter(|(_, range)| {
+            range.split_once('-').is_some_and(|(start, end)| {
+                [start, end]
+                    .iter()
+                    .all(|v| !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()))
+            })
+        });
+
+        if let Some((path, range)) = suffix {
+            return Ok(Self {
+                path: PathBuf::from(path),
+                range: Some(parse_frame_range(range)?),
+            });
+        }
+
+        Ok(Self {
+            path: PathBuf::from(value),
+            range: None,
+        })
+    }
+
+    fn contains(&self, frame: usize) -> bool {
+        self.range
+            .is_none_or(|(start, end)| (start..=end).contains(&frame))
+    }
+}
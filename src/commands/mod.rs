@@ -1,16 +1,25 @@
 use std::path::PathBuf;
 
-use anyhow::{Result, bail};
-use clap::{Args, Parser, ValueHint};
+use anyhow::{Context, Result, bail, ensure};
+use clap::{ArgGroup, Args, Parser, ValueHint};
 use hdr10plus::metadata::PeakBrightnessSource;
 
 use crate::CliOptions;
+use crate::core::parser::DuplicatePolicy;
+use crate::utils::parse_frame_rate;
+use merge::{MergeInput, MixedProfiles};
 
+pub mod convert;
+pub mod cut;
+pub mod diff;
 pub mod editor;
 pub mod extract;
+pub mod info;
 pub mod inject;
+pub mod merge;
 pub mod plot;
 pub mod remove;
+pub mod split;
 
 #[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ArgPeakBrightnessSource {
@@ -43,6 +52,27 @@ pub enum Command {
 
     #[command(about = "Edit the HDR10+ metadata")]
     Editor(EditorArgs),
+
+    #[command(about = "Converts HDR10+ metadata between the JSON and binary formats")]
+    Convert(ConvertArgs),
+
+    #[command(about = "Prints a summary of the HDR10+ metadata of a file")]
+    Info(InfoArgs),
+
+    #[command(about = "Compares the HDR10+ metadata of two files frame by frame")]
+    Diff(DiffArgs),
+
+    #[command(
+        about = "Concatenates the HDR10+ metadata of multiple files, with optional frame ranges",
+        visible_alias = "concat"
+    )]
+    Merge(MergeArgs),
+
+    #[command(about = "Splits the HDR10+ metadata into multiple JSON files")]
+    Split(SplitArgs),
+
+    #[command(about = "Cuts a frame range of an HEVC bitstream, with its HDR10+ metadata as JSON")]
+    Cut(CutArgs),
 }
 
 #[derive(Args, Debug)]
@@ -88,6 +118,33 @@ pub struct ExtractArgs {
         help = "Stop processing input after N frames"
     )]
     pub limit: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Report the layers and frames the HDR10+ SEI messages were found in. Only the base layer is extracted"
+    )]
+    pub report_layers: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = DuplicatePolicy::First,
+        help = "Which metadata to keep when a frame has more than one HDR10+ SEI message"
+    )]
+    pub duplicates: DuplicatePolicy,
+
+    #[arg(
+        long,
+        help = "Skip undecodable HDR10+ payloads instead of failing, the frames reuse the previous metadata"
+    )]
+    pub lenient: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Parse and validate the metadata payloads on N threads. 0 uses all available cores"
+    )]
+    pub threads: Option<usize>,
 }
 
 #[derive(Args, Debug)]
@@ -115,7 +172,7 @@ pub struct InjectArgs {
     #[arg(
         long,
         short = 'j',
-        help = "Sets the input JSON file to use",
+        help = "Sets the input JSON or binary metadata file to use",
         value_hint = ValueHint::FilePath
     )]
     pub json: PathBuf,
@@ -127,6 +184,31 @@ pub struct InjectArgs {
         value_hint = ValueHint::FilePath
     )]
     pub output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Re-parse the output file and verify the HDR10+ metadata matches the input metadata",
+        conflicts_with = "dry_run"
+    )]
+    pub verify_output: bool,
+
+    #[arg(
+        long,
+        help = "Keep the start code of every NAL unit from the input, instead of 4 bytes start codes"
+    )]
+    pub preserve_start_codes: bool,
+
+    #[arg(
+        long,
+        help = "Append the HDR10+ SEI message to the existing prefix SEI NAL unit of the frame, if there is one"
+    )]
+    pub merge_prefix_sei: bool,
+
+    #[arg(
+        long,
+        help = "Process the input without writing anything, and report the changes that would be made"
+    )]
+    pub dry_run: bool,
 }
 
 #[derive(Args, Debug)]
@@ -158,13 +240,25 @@ pub struct RemoveArgs {
         value_hint = ValueHint::FilePath
     )]
     pub output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Keep the start code of every NAL unit from the input, instead of 4 bytes start codes"
+    )]
+    pub preserve_start_codes: bool,
+
+    #[arg(
+        long,
+        help = "Process the input without writing anything, and report the changes that would be made"
+    )]
+    pub dry_run: bool,
 }
 
 #[derive(Args, Debug)]
 pub struct PlotArgs {
     #[arg(
         id = "input",
-        help = "Sets the input JSON file to use",
+        help = "Sets the input JSON or binary metadata file to use",
         long,
         short = 'i',
         conflicts_with = "input_pos",
@@ -175,7 +269,7 @@ pub struct PlotArgs {
 
     #[arg(
         id = "input_pos",
-        help = "Sets the input JSON file to use (positional)",
+        help = "Sets the input JSON or binary metadata file to use (positional)",
         conflicts_with = "input",
         required_unless_present = "input",
         value_hint = ValueHint::FilePath
@@ -213,7 +307,7 @@ pub struct PlotArgs {
 pub struct EditorArgs {
     #[arg(
         id = "input",
-        help = "Sets the input JSON file to use",
+        help = "Sets the input JSON or binary metadata file to use",
         long,
         short = 'i',
         conflicts_with = "input_pos",
@@ -224,7 +318,7 @@ pub struct EditorArgs {
 
     #[arg(
         id = "input_pos",
-        help = "Sets the input JSON file to use (positional)",
+        help = "Sets the input JSON or binary metadata file to use (positional)",
         conflicts_with = "input",
         required_unless_present = "input",
         value_hint = ValueHint::FilePath
@@ -243,10 +337,250 @@ pub struct EditorArgs {
     #[arg(
         long,
         short = 'o',
-        help = "Modified JSON output file location",
+        help = "Modified metadata output file location, in the same format as the input",
         value_hint = ValueHint::FilePath
     )]
     pub json_out: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_parser = parse_frame_rate,
+        help = "Frame rate used to convert the timecodes and timestamps of the edits to frames, like 24000/1001"
+    )]
+    pub fps: Option<f64>,
+
+    #[arg(
+        long,
+        conflicts_with = "fps",
+        help = "Reads the frame rate from the VUI timing information of a HEVC file, instead of --fps",
+        value_hint = ValueHint::FilePath
+    )]
+    pub input_hevc: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+    #[arg(
+        id = "input",
+        help = "Sets the input JSON or binary metadata file to use",
+        long,
+        short = 'i',
+        conflicts_with = "input_pos",
+        required_unless_present = "input_pos",
+        value_hint = ValueHint::FilePath,
+    )]
+    pub input: Option<PathBuf>,
+
+    #[arg(
+        id = "input_pos",
+        help = "Sets the input JSON or binary metadata file to use (positional)",
+        conflicts_with = "input",
+        required_unless_present = "input",
+        value_hint = ValueHint::FilePath
+    )]
+    pub input_pos: Option<PathBuf>,
+
+    #[arg(
+        long,
+        short = 'o',
+        help = "Output file location, in the other format than the input",
+        value_hint = ValueHint::FilePath
+    )]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct InfoArgs {
+    #[arg(
+        id = "input",
+        help = "Sets the input JSON, binary metadata or HEVC file to use, or piped with -",
+        long,
+        short = 'i',
+        conflicts_with = "input_pos",
+        required_unless_present = "input_pos",
+        value_hint = ValueHint::FilePath,
+    )]
+    pub input: Option<PathBuf>,
+
+    #[arg(
+        id = "input_pos",
+        help = "Sets the input JSON, binary metadata or HEVC file to use, or piped with - (positional)",
+        conflicts_with = "input",
+        required_unless_present = "input",
+        value_hint = ValueHint::FilePath
+    )]
+    pub input_pos: Option<PathBuf>,
+
+    #[arg(long, help = "Print the summary as JSON")]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    #[arg(
+        help = "First JSON, binary metadata or HEVC file to compare, or piped with -",
+        value_hint = ValueHint::FilePath
+    )]
+    pub first: PathBuf,
+
+    #[arg(
+        help = "Second JSON, binary metadata or HEVC file to compare",
+        value_hint = ValueHint::FilePath
+    )]
+    pub second: PathBuf,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        allow_negative_numbers = true,
+        help = "Frame N of the first file is compared to frame N + offset of the second file"
+    )]
+    pub offset: i64,
+
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        help = "Maximum difference in nits for the luminance values to be considered equal"
+    )]
+    pub tolerance: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct MergeArgs {
+    #[arg(
+        required = true,
+        value_parser = MergeInput::parse,
+        help = "JSON or binary metadata files to merge, in order. A frame range can be added as `file.json:start-end` (inclusive)",
+        value_hint = ValueHint::FilePath
+    )]
+    pub inputs: Vec<MergeInput>,
+
+    #[arg(
+        long,
+        short = 'o',
+        help = "Merged JSON output file location",
+        value_hint = ValueHint::FilePath
+    )]
+    pub output: PathBuf,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = MixedProfiles::Error,
+        help = "How to merge inputs with both profile A and profile B metadata"
+    )]
+    pub mixed_profiles: MixedProfiles,
+}
+
+#[derive(Args, Debug)]
+#[command(group = ArgGroup::new("split").required(true).args(["ranges", "scenes", "chapters"]))]
+pub struct SplitArgs {
+    #[arg(
+        id = "input",
+        help = "Sets the input JSON or binary metadata file to use",
+        long,
+        short = 'i',
+        conflicts_with = "input_pos",
+        required_unless_present = "input_pos",
+        value_hint = ValueHint::FilePath,
+    )]
+    pub input: Option<PathBuf>,
+
+    #[arg(
+        id = "input_pos",
+        help = "Sets the input JSON or binary metadata file to use (positional)",
+        conflicts_with = "input",
+        required_unless_present = "input",
+        value_hint = ValueHint::FilePath
+    )]
+    pub input_pos: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = parse_frame_range,
+        help = "Frame ranges of the outputs, as start-end (inclusive) separated by commas"
+    )]
+    pub ranges: Option<Vec<(usize, usize)>>,
+
+    #[arg(long, value_name = "N", help = "Split every N scenes")]
+    pub scenes: Option<usize>,
+
+    #[arg(
+        long,
+        requires = "fps",
+        help = "Split at the chapters of an OGM or Matroska XML chapter file",
+        value_hint = ValueHint::FilePath
+    )]
+    pub chapters: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_parser = parse_frame_rate,
+        help = "Frame rate used to convert the chapter timestamps to frames, like 24000/1001"
+    )]
+    pub fps: Option<f64>,
+
+    #[arg(
+        long,
+        short = 'o',
+        default_value = ".",
+        help = "Output directory of the JSON files",
+        value_hint = ValueHint::DirPath
+    )]
+    pub output_dir: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct CutArgs {
+    #[arg(
+        id = "input",
+        help = "Sets the input HEVC file to use",
+        long,
+        short = 'i',
+        conflicts_with = "input_pos",
+        required_unless_present = "input_pos",
+        value_hint = ValueHint::FilePath,
+    )]
+    pub input: Option<PathBuf>,
+
+    #[arg(
+        id = "input_pos",
+        help = "Sets the input HEVC file to use (positional)",
+        conflicts_with = "input",
+        required_unless_present = "input",
+        value_hint = ValueHint::FilePath
+    )]
+    pub input_pos: Option<PathBuf>,
+
+    #[arg(
+        long,
+        short = 'r',
+        value_parser = parse_frame_range,
+        help = "Frame range to keep, as start-end (inclusive) in presentation order"
+    )]
+    pub range: (usize, usize),
+
+    #[arg(
+        long,
+        short = 'o',
+        help = "Output HEVC file location",
+        value_hint = ValueHint::FilePath
+    )]
+    pub output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Output JSON file location. Defaults to the output HEVC file with a .json extension",
+        value_hint = ValueHint::FilePath
+    )]
+    pub json_output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Keep the start code of every NAL unit from the input, instead of 4 bytes start codes"
+    )]
+    pub preserve_start_codes: bool,
 }
 
 pub fn input_from_either(cmd: &str, in1: Option<PathBuf>, in2: Option<PathBuf>) -> Result<PathBuf> {
@@ -262,6 +596,24 @@ pub fn input_from_either(cmd: &str, in1: Option<PathBuf>, in2: Option<PathBuf>)
     }
 }
 
+/// Inclusive frame range, formatted as `start-end`
+pub fn parse_frame_range(range: &str) -> Result<(usize, usize)> {
+    let (start, end) = range
+        .split_once('-')
+        .with_context(|| format!("Invalid frame range {range}, expected start-end"))?;
+
+    let start: usize = start
+        .parse()
+        .with_context(|| format!("Invalid frame range start {start}"))?;
+    let end: usize = end
+        .parse()
+        .with_context(|| format!("Invalid frame range end {end}"))?;
+
+    ensure!(start <= end, "Invalid range {range}, start is after end");
+
+    Ok((start, end))
+}
+
 impl From<ArgPeakBrightnessSource> for PeakBrightnessSource {
     fn from(e: ArgPeakBrightnessSource) -> Self {
         match e {
@@ -4,9 +4,8 @@ use std::path::PathBuf;
 #[cfg(not(feature = "system-font"))]
 use anyhow::bail;
 
-use anyhow::Result;
-use hdr10plus::metadata::{PeakBrightnessSource, VariablePeakBrightness};
-use hdr10plus::metadata_json::{Hdr10PlusJsonMetadata, MetadataJsonRoot};
+use anyhow::{Context, Result, ensure};
+use hdr10plus::metadata::{Hdr10PlusMetadata, PeakBrightnessSource, VariablePeakBrightness};
 use plotters::coord::ranged1d::{KeyPointHint, NoDefaultFormatting, Ranged, ValueFormatter};
 use plotters::coord::types::RangedCoordusize;
 use plotters::prelude::{
@@ -15,6 +14,7 @@ use plotters::prelude::{
 };
 use plotters::style::{BLACK, Color, IntoTextStyle, RGBColor, ShapeStyle};
 
+use crate::core::metadata_file::MetadataReader;
 use crate::utils::{nits_to_pq, pq_to_nits};
 
 use super::{PlotArgs, input_from_either};
@@ -67,16 +67,40 @@ impl Plotter {
             peak_brightness_source: PeakBrightnessSource::from(peak_source),
         };
 
-        println!("Parsing JSON file...");
-        let metadata_root = MetadataJsonRoot::from_file(&plotter.input)?;
-        let frames_orig = metadata_root.scene_info;
+        println!("Parsing metadata file...");
+        let mut reader = MetadataReader::from_file(&plotter.input)?;
 
-        // inclusive range, end must be last frame index
+        // Only the plotted values of the frames in range are kept
         let start = start_arg.unwrap_or(0);
-        let end = end_arg.unwrap_or(frames_orig.len() - 1);
-        let frames = &frames_orig[start..=end];
+        let mut data = Vec::new();
+
+        while let Some(frame) = reader.next_frame()? {
+            let i = reader.frame_count() - 1;
+
+            if i >= start && end_arg.is_none_or(|end| i <= end) {
+                data.push(plotter.brightness_values(&frame, i)?);
+            }
+        }
 
-        let x_spec = 0..frames.len();
+        let frame_count = reader.frame_count();
+        ensure!(frame_count > 0, "No metadata frames in JSON file");
+
+        // inclusive range, end must be last frame index
+        let end = end_arg.unwrap_or(frame_count - 1);
+        ensure!(
+            start <= end,
+            "Invalid frame range: start {start} is after end {end}"
+        );
+        ensure!(
+            end < frame_count,
+            "Invalid frame range: end {end} is out of bounds, last frame is {}",
+            frame_count - 1
+        );
+
+        let profile = reader.profile().context("Missing JSONInfo")?;
+        let scene_count = reader.scene_count().context("Missing SceneInfoSummary")?;
+
+        let x_spec = 0..data.len();
 
         let root = BitMapBackend::new(&output, (3000, 1200)).into_drawing_area();
         root.fill(&WHITE)?;
@@ -104,7 +128,7 @@ impl Plotter {
             .y_desc("nits (cd/m²)")
             .draw()?;
 
-        plotter.draw_brightness_series(&mut chart, frames)?;
+        plotter.draw_brightness_series(&mut chart, &data)?;
         chart
             .configure_series_labels()
             .border_style(BLACK)
@@ -116,9 +140,9 @@ impl Plotter {
         let caption_style = ("sans-serif", 24).into_text_style(&root);
         let chart_caption = format!(
             "Frames: {}. Profile {}. Scenes: {}.",
-            frames.len(),
-            metadata_root.info.profile,
-            metadata_root.scene_info_summary.scene_frame_numbers.len()
+            data.len(),
+            profile,
+            scene_count
         );
         root.draw_text(&chart_caption, &caption_style, (60, 35))?;
 
@@ -132,33 +156,29 @@ impl Plotter {
         Ok(())
     }
 
+    /// Average and peak brightness of the frame, in PQ
+    fn brightness_values(&self, frame: &Hdr10PlusMetadata, i: usize) -> Result<(f64, f64)> {
+        let avg_nits = frame.average_maxrgb as f64 / 10.0;
+        let peak_nits = frame
+            .peak_brightness_nits(self.peak_brightness_source)
+            .with_context(|| {
+                format!(
+                    "Frame {i}: no peak brightness for source: {}",
+                    self.peak_brightness_source
+                )
+            })?;
+
+        Ok((nits_to_pq(avg_nits), nits_to_pq(peak_nits)))
+    }
+
     fn draw_brightness_series(
         &self,
         chart: &mut ChartContext<BitMapBackend, Cartesian2d<RangedCoordusize, PqCoord>>,
-        frames: &[Hdr10PlusJsonMetadata],
+        data: &[(f64, f64)],
     ) -> Result<()> {
-        let data: Vec<_> = frames
-            .iter()
-            .map(|f| {
-                let lum_params = &f.luminance_parameters;
-                let avg_nits = lum_params.average_rgb as f64 / 10.0;
-                let peak_nits = f.peak_brightness_nits(self.peak_brightness_source).unwrap();
-
-                (nits_to_pq(avg_nits), nits_to_pq(peak_nits))
-            })
-            .collect();
-
-        let maxfall = data
-            .iter()
-            .map(|e| e.0)
-            .max_by(|a, b| a.partial_cmp(b).unwrap())
-            .unwrap();
+        let maxfall = data.iter().map(|e| e.0).fold(0.0, f64::max);
         let maxfall_avg = data.iter().map(|e| e.0).sum::<f64>() / data.len() as f64;
-        let maxcll = data
-            .iter()
-            .map(|e| e.1)
-            .max_by(|a, b| a.partial_cmp(b).unwrap())
-            .unwrap();
+        let maxcll = data.iter().map(|e| e.1).fold(0.0, f64::max);
         let maxcll_avg = data.iter().map(|e| e.1).sum::<f64>() / data.len() as f64;
 
         let avg_series_label = format!(
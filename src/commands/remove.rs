@@ -1,22 +1,31 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
 
 use anyhow::{Result, bail};
 use hevc_parser::HevcParser;
-use hevc_parser::hevc::{NAL_SEI_PREFIX, NALUnit};
+use hevc_parser::hevc::{NAL_SEI_SUFFIX, NALUnit};
 use hevc_parser::io::processor::{HevcProcessor, HevcProcessorOpts};
 use indicatif::ProgressBar;
 
 use hevc_parser::io::{IoFormat, IoProcessor};
 
 use super::{CliOptions, RemoveArgs, input_from_either};
-use crate::core::{initialize_progress_bar, prefix_sei_removed_hdr10plus_nalu};
+use crate::core::{
+    ChangeReport, CountingReader, OutputWriter, StartCodeTracker, initialize_progress_bar,
+    is_sei_nal_type, sei_removed_hdr10plus_nalu,
+};
 
 pub struct Remover {
     input: PathBuf,
     progress_bar: ProgressBar,
-    writer: BufWriter<File>,
+    writer: OutputWriter,
+
+    dry_run: bool,
+    preserve_start_codes: bool,
+
+    start_code_tracker: StartCodeTracker,
+    report: ChangeReport,
 }
 
 impl Remover {
@@ -25,6 +34,8 @@ impl Remover {
             input,
             input_pos,
             output,
+            preserve_start_codes,
+            dry_run,
         } = args;
         let input = input_from_either("remove", input, input_pos)?;
 
@@ -41,13 +52,20 @@ impl Remover {
 
         let pb = initialize_progress_bar(&format, &input)?;
 
+        let writer = if dry_run {
+            OutputWriter::dry_run()
+        } else {
+            OutputWriter::new(hevc_out, 100_000)?
+        };
+
         let mut remover = Remover {
             input,
             progress_bar: pb,
-            writer: BufWriter::with_capacity(
-                100_000,
-                File::create(hevc_out).expect("Can't create file"),
-            ),
+            writer,
+            dry_run,
+            preserve_start_codes,
+            start_code_tracker: StartCodeTracker::default(),
+            report: ChangeReport::default(),
         };
 
         remover.process_input(&format)
@@ -56,8 +74,9 @@ impl Remover {
     pub fn process_input(&mut self, format: &IoFormat) -> Result<()> {
         let chunk_size = 100_000;
 
+        // Parsing is only needed to count the access units
         let processor_opts = HevcProcessorOpts {
-            parse_nals: false,
+            parse_nals: self.dry_run,
             ..Default::default()
         };
         let mut processor = HevcProcessor::new(format.clone(), processor_opts, chunk_size);
@@ -70,7 +89,17 @@ impl Remover {
             reader = Box::new(BufReader::with_capacity(100_000, file));
         }
 
-        processor.process_io(&mut reader, self)
+        let mut reader = CountingReader::new(reader);
+        processor.process_io(&mut reader, self)?;
+
+        if self.dry_run {
+            self.report.input_size = reader.read_bytes();
+            self.report.output_size = self.writer.written_bytes();
+
+            self.report.print();
+        }
+
+        Ok(())
     }
 }
 
@@ -87,13 +116,25 @@ impl IoProcessor for Remover {
         for nal in nals {
             let mut nalu_data_override = None;
 
-            if nal.nal_type == NAL_SEI_PREFIX {
-                let (has_st2094_40, data) = prefix_sei_removed_hdr10plus_nalu(chunk, nal)?;
+            if is_sei_nal_type(nal.nal_type) {
+                let (st2094_40_count, data) = sei_removed_hdr10plus_nalu(chunk, nal, false)?;
+
+                if nal.nal_type == NAL_SEI_SUFFIX {
+                    self.report.suffix_hdr10plus_seis += st2094_40_count;
+                }
+
+                // Drop NALUs containing only HDR10+ SEI messages
+                if st2094_40_count > 0 && data.is_none() {
+                    self.report.standalone_hdr10plus_seis += st2094_40_count;
+                    self.report.nals_dropped += 1;
 
-                // Drop NALUs containing only one SEI message
-                if has_st2094_40 && data.is_none() {
                     continue;
                 } else {
+                    if st2094_40_count > 0 {
+                        self.report.shared_hdr10plus_seis += st2094_40_count;
+                        self.report.nals_rewritten += 1;
+                    }
+
                     nalu_data_override = data;
                 }
             }
@@ -103,22 +144,35 @@ impl IoProcessor for Remover {
                 .map(|e| e.as_ref())
                 .unwrap_or(&chunk[nal.start..nal.end]);
 
-            NALUnit::write_with_preset(
-                &mut self.writer,
-                data,
-                hevc_parser::io::StartCodePreset::Four,
-                nal.nal_type,
-                false,
-            )?;
+            if self.preserve_start_codes {
+                let start_code = self.start_code_tracker.start_code(nal);
+
+                self.writer.write_all(start_code.slice())?;
+                self.writer.write_all(data)?;
+            } else {
+                NALUnit::write_with_preset(
+                    &mut self.writer,
+                    data,
+                    hevc_parser::io::StartCodePreset::Four,
+                    nal.nal_type,
+                    false,
+                )?;
+            }
         }
 
+        self.start_code_tracker.finish_chunk(nals, chunk);
+
         Ok(())
     }
 
-    fn finalize(&mut self, _parser: &HevcParser) -> Result<()> {
+    fn finalize(&mut self, parser: &HevcParser) -> Result<()> {
         self.progress_bar.finish_and_clear();
         self.writer.flush()?;
 
+        if self.dry_run {
+            self.report.access_units = parser.ordered_frames().len();
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,253 @@
+use std::fs::File;
+use std::io::{BufWriter, Write, stdout};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+
+use hdr10plus::metadata_json::{MetadataJsonWriter, metadata_list_profile};
+
+use super::{SplitArgs, input_from_either};
+use crate::core::metadata_file::{MetadataReader, combined_profile};
+use crate::core::parser::{TOOL_NAME, TOOL_VERSION};
+use crate::utils::{parse_timestamp, timestamp_to_frame};
+
+pub struct Splitter {
+    input: PathBuf,
+    output_dir: PathBuf,
+}
+
+/// Profile and version of every frame, from the first pass
+struct FrameProfiles {
+    profiles: Vec<&'static str>,
+    versions: Vec<u8>,
+    scene_first_frames: Vec<usize>,
+}
+
+/// Output file and its frame range, inclusive
+struct Split {
+    path: PathBuf,
+    start: usize,
+    end: usize,
+    writer: Option<MetadataJsonWriter<BufWriter<File>>>,
+}
+
+impl Splitter {
+    pub fn split(args: SplitArgs) -> Result<()> {
+        let SplitArgs {
+            input,
+            input_pos,
+            ranges,
+            scenes,
+            chapters,
+            fps,
+            output_dir,
+        } = args;
+
+        let input = input_from_either("split", input, input_pos)?;
+        let splitter = Splitter { input, output_dir };
+
+        println!("Reading metadata file...");
+        let frames = splitter.read_profiles()?;
+        let frame_count = frames.profiles.len();
+
+        let ranges = if let Some(ranges) = ranges {
+            ranges
+        } else if let Some(scenes) = scenes {
+            ensure!(
+                scenes > 0,
+                "The number of scenes per split must be at least 1"
+            );
+            ranges_from_starts(
+                frames.scene_first_frames.iter().step_by(scenes).copied(),
+                frame_count,
+            )
+        } else if let Some(chapters) = chapters {
+            let fps = fps.ok_or_else(|| anyhow!("--fps is required to split by chapters"))?;
+            let starts = chapter_frames(&chapters, fps)?;
+
+            ranges_from_starts(starts.into_iter(), frame_count)
+        } else {
+            bail!("No split given, use --ranges, --scenes or --chapters");
+        };
+
+        ensure!(!ranges.is_empty(), "No frame range to split");
+
+        for (start, end) in &ranges {
+            ensure!(
+                *end < frame_count,
+                "Invalid range {start}-{end}, the metadata has {frame_count} frames"
+            );
+        }
+
+        splitter.write_splits(&frames, &ranges)
+    }
+
+    fn read_profiles(&self) -> Result<FrameProfiles> {
+        let mut reader = MetadataReader::from_file(&self.input)?;
+        let mut profiles = Vec::new();
+        let mut versions = Vec::new();
+
+        while let Some(metadata) = reader.next_frame()? {
+            profiles.push(metadata_list_profile(&[&metadata]));
+            versions.push(metadata.application_version);
+        }
+
+        let scene_first_frames = reader.scene_first_frames().unwrap_or_else(|| vec![0]);
+
+        Ok(FrameProfiles {
+            profiles,
+            versions,
+            scene_first_frames,
+        })
+    }
+
+    /// The outputs are written at the same time, in one pass
+    fn write_splits(&self, frames: &FrameProfiles, ranges: &[(usize, usize)]) -> Result<()> {
+        let file_stem = self
+            .input
+            .file_stem()
+            .ok_or_else(|| anyhow!("Invalid input file name: {}", self.input.display()))?
+            .to_string_lossy();
+        let width = ranges.len().to_string().len();
+
+        let mut splits: Vec<Split> = ranges
+            .iter()
+            .enumerate()
+            .map(|(i, &(start, end))| Split {
+                path: self
+                    .output_dir
+                    .join(format!("{file_stem}_part{:0width$}.json", i + 1)),
+                start,
+                end,
+                writer: None,
+            })
+            .collect();
+
+        print!("Writing {} JSON files... ", splits.len());
+        stdout().flush().ok();
+
+        let mut reader = MetadataReader::from_file(&self.input)?;
+
+        while let Some(metadata) = reader.next_frame()? {
+            let frame = reader.frame_count() - 1;
+
+            for split in splits
+                .iter_mut()
+                .filter(|s| (s.start..=s.end).contains(&frame))
+            {
+                if split.writer.is_none() {
+                    split.writer = Some(split.create_writer(frames)?);
+                }
+
+                if let Some(writer) = &mut split.writer {
+                    writer.write_frame(metadata.clone())?;
+                }
+
+                if frame == split.end {
+                    split.finish()?;
+                }
+            }
+        }
+
+        println!("Done.");
+
+        for split in &splits {
+            println!(
+                "  {}: frames {}-{} ({} frames)",
+                split.path.display(),
+                split.start,
+                split.end,
+                split.end - split.start + 1
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Split {
+    fn create_writer(&self, frames: &FrameProfiles) -> Result<MetadataJsonWriter<BufWriter<File>>> {
+        let profile = frames.profiles[self.start..=self.end]
+            .iter()
+            .fold(None, |profile, frame_profile| {
+                Some(combined_profile(profile, frame_profile))
+            })
+            .unwrap_or("N/A");
+        let version = frames.versions[self.start];
+
+        let file = File::create(&self.path)
+            .with_context(|| format!("Failed creating output file {}", self.path.display()))?;
+
+        MetadataJsonWriter::new(BufWriter::with_capacity(1_000_000, file), profile, version)
+    }
+
+    /// The scene information is renumbered from zero in every output
+    fn finish(&mut self) -> Result<()> {
+        if let Some(json_writer) = self.writer.take() {
+            let mut writer = json_writer.finish(TOOL_NAME, TOOL_VERSION)?;
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Consecutive ranges starting at each frame, the last one ends at the last frame
+fn ranges_from_starts<I>(starts: I, frame_count: usize) -> Vec<(usize, usize)>
+where
+    I: Iterator<Item = usize>,
+{
+    let mut starts: Vec<usize> = starts.filter(|start| *start < frame_count).collect();
+    starts.sort_unstable();
+    starts.dedup();
+
+    // The frames before the first start are part of the first range
+    if let Some(first) = starts.first_mut() {
+        *first = 0;
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).map_or(frame_count, |next| *next) - 1;
+            (start, end)
+        })
+        .collect()
+}
+
+/// Start frames of the chapters, from an OGM (`CHAPTER01=00:00:00.000`) or Matroska XML chapter file
+fn chapter_frames(path: &Path, fps: f64) -> Result<Vec<usize>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed reading chapter file {}", path.display()))?;
+
+    let timestamps: Vec<&str> = if content.contains("<ChapterTimeStart>") {
+        content
+            .split("<ChapterTimeStart>")
+            .skip(1)
+            .filter_map(|part| part.split_once("</ChapterTimeStart>"))
+            .map(|(timestamp, _)| timestamp)
+            .collect()
+    } else {
+        content
+            .lines()
+            .filter_map(|line| line.trim().split_once('='))
+            .filter(|(key, _)| {
+                key.starts_with("CHAPTER") && key[7..].bytes().all(|b| b.is_ascii_digit())
+            })
+            .map(|(_, timestamp)| timestamp)
+            .collect()
+    };
+
+    ensure!(
+        !timestamps.is_empty(),
+        "No chapters found in {}",
+        path.display()
+    );
+
+    timestamps
+        .into_iter()
+        .map(|timestamp| parse_timestamp(timestamp).map(|seconds| timestamp_to_frame(seconds, fps)))
+        .collect()
+}
@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use hevc_parser::io::format_from_path;
+
+use hdr10plus::metadata::Hdr10PlusMetadata;
+use hdr10plus::metadata_binary::{MetadataBinaryReader, is_metadata_binary};
+use hdr10plus::metadata_json::{MetadataJsonReader, metadata_list_profile};
+
+use crate::CliOptions;
+
+use super::initialize_progress_bar;
+use super::parser::{ExtractedFrames, Parser, ParserOptions};
+
+/// Format of a metadata file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataFormat {
+    Json,
+    Binary,
+}
+
+/// Reads the frames of a JSON or binary metadata file, one at a time.
+/// The metadata can also be extracted from a HEVC file with `from_source`.
+pub enum MetadataReader {
+    Json(MetadataJsonReader<BufReader<File>>),
+    Binary {
+        reader: MetadataBinaryReader<BufReader<File>>,
+        /// Profile of the frames read so far
+        profile: Option<&'static str>,
+    },
+    Hevc(Box<ExtractedFrames>),
+}
+
+impl MetadataFormat {
+    /// Detected from the content of the file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let is_binary = is_metadata_binary(path)
+            .with_context(|| format!("Failed reading metadata file {}", path.display()))?;
+
+        Ok(if is_binary { Self::Binary } else { Self::Json })
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Binary => "bin",
+        }
+    }
+}
+
+impl MetadataReader {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let reader = match MetadataFormat::from_file(path)? {
+            MetadataFormat::Json => Self::Json(MetadataJsonReader::from_file(path)?),
+            MetadataFormat::Binary => Self::Binary {
+                reader: MetadataBinaryReader::from_file(path)?,
+                profile: None,
+            },
+        };
+
+        Ok(reader)
+    }
+
+    /// Same as `from_file`, also accepting HEVC files (or piped with `-`).
+    /// The metadata of HEVC files is extracted first, with the default extract options.
+    pub fn from_source(path: &Path, options: CliOptions) -> Result<Self> {
+        let hevc_format = match format_from_path(path) {
+            Ok(format) if !is_metadata_binary(path).unwrap_or(false) => Some(format),
+            _ => None,
+        };
+
+        let Some(format) = hevc_format else {
+            return Self::from_file(path);
+        };
+
+        let pb = initialize_progress_bar(&format, path)?;
+        let mut parser = Parser::new(
+            path.to_path_buf(),
+            None,
+            options,
+            pb,
+            false,
+            ParserOptions {
+                quiet: true,
+                ..Default::default()
+            },
+        );
+
+        parser.process_input(&format)?;
+
+        Ok(Self::Hevc(Box::new(parser.take_frames()?)))
+    }
+
+    /// Reads the next frame, `None` once all the frames were read
+    pub fn next_frame(&mut self) -> Result<Option<Hdr10PlusMetadata>> {
+        let frame = self.frame_count();
+
+        match self {
+            Self::Json(reader) => reader
+                .next_frame()?
+                .map(|meta| {
+                    Hdr10PlusMetadata::try_from(&meta)
+                        .with_context(|| format!("Invalid metadata for frame {frame}"))
+                })
+                .transpose(),
+            Self::Binary { reader, profile } => {
+                let metadata = reader.next_frame()?;
+
+                if let Some(metadata) = &metadata {
+                    let frame_profile = metadata_list_profile(&[metadata]);
+                    *profile = Some(combined_profile(*profile, frame_profile));
+                }
+
+                Ok(metadata)
+            }
+            Self::Hevc(frames) => frames.next_frame(),
+        }
+    }
+
+    /// Number of frames read so far
+    pub fn frame_count(&self) -> usize {
+        match self {
+            Self::Json(reader) => reader.frame_count(),
+            Self::Binary { reader, .. } => reader.frame_count(),
+            Self::Hevc(frames) => frames.frame_count(),
+        }
+    }
+
+    /// The JSON's profile, or the profile of the binary frames read so far
+    pub fn profile(&self) -> Option<&str> {
+        match self {
+            Self::Json(reader) => reader.info().map(|info| info.profile.as_str()),
+            Self::Binary { profile, .. } => *profile,
+            Self::Hevc(frames) => Some(frames.profile()),
+        }
+    }
+
+    /// Only available once all the frames were read
+    pub fn scene_count(&self) -> Option<usize> {
+        self.scene_first_frames()
+            .map(|first_frames| first_frames.len())
+    }
+
+    /// First frame of every scene, as stored in the file or computed for HEVC files.
+    ///
+    /// Only available once all the frames were read
+    pub fn scene_first_frames(&self) -> Option<Vec<usize>> {
+        match self {
+            Self::Json(reader) => reader
+                .scene_info_summary()
+                .map(|summary| summary.scene_first_frame_index.clone()),
+            Self::Binary { reader, .. } => reader
+                .scene_first_frames()
+                .map(|index| index.iter().map(|&frame| frame as usize).collect()),
+            Self::Hevc(frames) => Some(frames.scene_first_frames().to_vec()),
+        }
+    }
+}
+
+/// Removes the Bezier curve and target display luminance, making the frame profile A
+pub fn convert_to_profile_a(metadata: &mut Hdr10PlusMetadata) {
+    metadata.tone_mapping_flag = false;
+    metadata.targeted_system_display_maximum_luminance = 0;
+    metadata.bezier_curve = None;
+
+    metadata.set_profile();
+}
+
+/// Profile of a list made of two parts, `N/A` when they are different
+pub fn combined_profile(previous: Option<&'static str>, profile: &'static str) -> &'static str {
+    match previous {
+        Some(previous) if previous != profile => "N/A",
+        _ => profile,
+    }
+}
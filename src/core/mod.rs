@@ -1,17 +1,28 @@
-use std::{fs::File, path::Path};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use indicatif::{ProgressBar, ProgressStyle};
 use thiserror::Error;
 
 use bitvec_helpers::bitstream_io_reader::BsIoSliceReader;
-use hevc_parser::hevc::{NAL_SEI_PREFIX, NALUnit, SeiMessage, USER_DATA_REGISTERED_ITU_T_35};
+use hevc_parser::NALUStartCode;
+use hevc_parser::hevc::{
+    Frame, NAL_SEI_PREFIX, NAL_SEI_SUFFIX, NAL_UNSPEC63, NALUnit, SeiMessage,
+    USER_DATA_REGISTERED_ITU_T_35,
+};
 use hevc_parser::io::IoFormat;
 use hevc_parser::utils::{
     add_start_code_emulation_prevention_3_byte, clear_start_code_emulation_prevention_3_byte,
 };
 
+pub mod metadata_file;
 pub mod parser;
+pub mod verifier;
+pub mod vui;
 
 #[derive(Error, Debug)]
 pub enum ParserError {
@@ -21,6 +32,351 @@ pub enum ParserError {
     MetadataDetected,
 }
 
+/// Layer a NAL unit belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NalLayer {
+    /// Base layer, `nuh_layer_id` 0
+    Base,
+    /// Layered HEVC, `nuh_layer_id` > 0
+    Layer(u8),
+    /// Dolby Vision enhancement layer NALU, encapsulated in a `NAL_UNSPEC63` NALU
+    DolbyVisionEl,
+}
+
+/// HDR10+ SEI messages found in a NAL unit
+pub struct Hdr10PlusSei {
+    pub layer: NalLayer,
+    /// Found in a suffix SEI NALU, which is not a conformant placement
+    pub suffix: bool,
+
+    /// Usually a single message, more when the metadata is duplicated
+    pub msgs: Vec<SeiMessage>,
+    /// SEI NALU bytes, with start_code_emulation_prevention_3_byte removed
+    pub sei_payload: Vec<u8>,
+}
+
+/// HEVC output writer, keeping track of the amount of bytes written.
+/// When created for a dry run, nothing is actually written.
+pub struct OutputWriter {
+    writer: Option<BufWriter<File>>,
+    written_bytes: u64,
+}
+
+/// Input reader keeping track of the amount of bytes read, also for piped input
+pub struct CountingReader<R: Read> {
+    reader: R,
+    read_bytes: u64,
+}
+
+/// Temporary file holding the HDR10+ payloads (or their JSON frames) in output order, length prefixed.
+///
+/// Created next to the output file, and removed when dropped.
+pub struct PayloadSpool {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    count: usize,
+}
+
+/// Reads back the payloads written to a `PayloadSpool`, in the same order
+pub struct PayloadSpoolReader {
+    reader: BufReader<File>,
+    remaining: usize,
+}
+
+/// Keeps track of the original NALU start codes across processed chunks.
+///
+/// The first NAL of a chunk is always reported with a 3 bytes start code,
+/// because the leading zero byte of a 4 bytes start code ends the previous chunk.
+#[derive(Debug, Default)]
+pub struct StartCodeTracker {
+    next_chunk_length4: bool,
+}
+
+/// Presentation number of the parsed frames, by decoded number.
+/// Avoids looking up the frame list for every decoded frame.
+#[derive(Debug, Default)]
+pub struct PresentationIndex {
+    presentation_numbers: HashMap<u64, u64>,
+}
+
+/// Summary of the changes made (or that would be made) to a bitstream
+#[derive(Debug, Default)]
+pub struct ChangeReport {
+    pub access_units: usize,
+
+    /// HDR10+ SEI messages alone in their NAL unit
+    pub standalone_hdr10plus_seis: usize,
+    /// HDR10+ SEI messages sharing a NAL unit with other SEI messages
+    pub shared_hdr10plus_seis: usize,
+    /// HDR10+ SEI messages in suffix SEI NAL units
+    pub suffix_hdr10plus_seis: usize,
+
+    pub nals_added: usize,
+    pub nals_dropped: usize,
+    pub nals_rewritten: usize,
+
+    pub input_size: u64,
+    pub output_size: u64,
+}
+
+impl OutputWriter {
+    pub fn new<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self> {
+        let file = File::create(path)?;
+
+        Ok(Self {
+            writer: Some(BufWriter::with_capacity(capacity, file)),
+            written_bytes: 0,
+        })
+    }
+
+    pub fn dry_run() -> Self {
+        Self {
+            writer: None,
+            written_bytes: 0,
+        }
+    }
+
+    pub fn written_bytes(&self) -> u64 {
+        self.written_bytes
+    }
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            read_bytes: 0,
+        }
+    }
+
+    pub fn read_bytes(&self) -> u64 {
+        self.read_bytes
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.read_bytes += read as u64;
+
+        Ok(read)
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = match self.writer.as_mut() {
+            Some(writer) => writer.write(buf)?,
+            None => buf.len(),
+        };
+
+        self.written_bytes += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl PayloadSpool {
+    /// The file is named after `output`, with a unique `.<pid>_<id>.part` extension appended
+    pub fn create(output: &Path) -> Result<Self> {
+        let mut path = output.as_os_str().to_owned();
+        path.push(format!(".{}.part", Self::unique_id()));
+
+        Self::create_at(PathBuf::from(path))
+    }
+
+    /// Created in the temporary directory, when there is no output file
+    pub fn create_temp() -> Result<Self> {
+        let file_name = format!("hdr10plus_tool_{}.part", Self::unique_id());
+
+        Self::create_at(std::env::temp_dir().join(file_name))
+    }
+
+    fn unique_id() -> String {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let id = COUNT.fetch_add(1, Ordering::Relaxed);
+        format!("{}_{id}", std::process::id())
+    }
+
+    /// Never replaces an existing file, which would be removed when dropped
+    fn create_at(path: PathBuf) -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| format!("Failed creating temporary file {}", path.display()))?;
+
+        Ok(Self {
+            path,
+            writer: BufWriter::with_capacity(1_000_000, file),
+            count: 0,
+        })
+    }
+
+    pub fn push(&mut self, payload: &[u8]) -> Result<()> {
+        let len = u32::try_from(payload.len()).context("HDR10+ payload too large")?;
+
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(payload)?;
+        self.count += 1;
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn reader(&mut self) -> Result<PayloadSpoolReader> {
+        self.writer.flush()?;
+
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed opening temporary file {}", self.path.display()))?;
+
+        Ok(PayloadSpoolReader {
+            reader: BufReader::with_capacity(1_000_000, file),
+            remaining: self.count,
+        })
+    }
+}
+
+impl Drop for PayloadSpool {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+impl PayloadSpoolReader {
+    /// Reads up to `max` payloads, the list is empty once all were read
+    pub fn read_batch(&mut self, max: usize) -> Result<Vec<Vec<u8>>> {
+        let count = self.remaining.min(max);
+        let mut payloads = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let mut len = [0; 4];
+            self.reader.read_exact(&mut len)?;
+
+            let mut payload = vec![0; u32::from_le_bytes(len) as usize];
+            self.reader.read_exact(&mut payload)?;
+
+            payloads.push(payload);
+        }
+
+        self.remaining -= count;
+
+        Ok(payloads)
+    }
+}
+
+impl NalLayer {
+    pub fn from_nal(nal: &NALUnit) -> Self {
+        if nal.nal_type == NAL_UNSPEC63 {
+            Self::DolbyVisionEl
+        } else if nal.nuh_layer_id > 0 {
+            Self::Layer(nal.nuh_layer_id)
+        } else {
+            Self::Base
+        }
+    }
+}
+
+impl std::fmt::Display for NalLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Base => f.write_str("Base layer"),
+            Self::Layer(id) => write!(f, "Layer {id}"),
+            Self::DolbyVisionEl => f.write_str("Dolby Vision enhancement layer (NAL_UNSPEC63)"),
+        }
+    }
+}
+
+impl StartCodeTracker {
+    /// Start code of the NAL, as it is in the input bitstream
+    pub fn start_code(&self, nal: &NALUnit) -> NALUStartCode {
+        // Only a NAL at the very start of the chunk can be affected
+        if self.next_chunk_length4 && nal.start == NALUStartCode::Length3.size() {
+            NALUStartCode::Length4
+        } else {
+            nal.start_code
+        }
+    }
+
+    /// Must be called once all the NALs of the chunk have been processed
+    pub fn finish_chunk(&mut self, nals: &[NALUnit], chunk: &[u8]) {
+        self.next_chunk_length4 = nals
+            .last()
+            .and_then(|nal| chunk.get(nal.end..nal.end + 4))
+            .is_some_and(|next| next == NALUStartCode::Length4.slice());
+    }
+}
+
+impl PresentationIndex {
+    pub fn new(frames: &[Frame]) -> Self {
+        let presentation_numbers = frames
+            .iter()
+            .map(|f| (f.decoded_number, f.presentation_number))
+            .collect();
+
+        Self {
+            presentation_numbers,
+        }
+    }
+
+    pub fn presentation_number(&self, decoded_number: u64) -> Option<u64> {
+        self.presentation_numbers.get(&decoded_number).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.presentation_numbers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.presentation_numbers.is_empty()
+    }
+}
+
+impl ChangeReport {
+    pub fn print(&self) {
+        let size_diff = self.output_size as i64 - self.input_size as i64;
+
+        println!("Dry run, no file was written.");
+        println!("  Access units: {}", self.access_units);
+        println!(
+            "  Existing HDR10+ SEI messages: {} ({} in their own NAL, {} sharing a NAL with other SEI messages)",
+            self.standalone_hdr10plus_seis + self.shared_hdr10plus_seis,
+            self.standalone_hdr10plus_seis,
+            self.shared_hdr10plus_seis
+        );
+        if self.suffix_hdr10plus_seis > 0 {
+            println!(
+                "  HDR10+ SEI messages in suffix SEI NALs (non-conformant placement): {}",
+                self.suffix_hdr10plus_seis
+            );
+        }
+
+        println!("  NALs added: {}", self.nals_added);
+        println!("  NALs dropped: {}", self.nals_dropped);
+        println!("  NALs rewritten: {}", self.nals_rewritten);
+        println!("  Input size: {} bytes", self.input_size);
+        println!(
+            "  Projected output size: {} bytes ({size_diff:+} bytes)",
+            self.output_size
+        );
+    }
+}
+
 pub fn initialize_progress_bar(format: &IoFormat, input: &Path) -> Result<ProgressBar> {
     let pb: ProgressBar;
     let bytes_count;
@@ -28,7 +384,8 @@ pub fn initialize_progress_bar(format: &IoFormat, input: &Path) -> Result<Progre
     if let IoFormat::RawStdin = format {
         pb = ProgressBar::hidden();
     } else {
-        let file = File::open(input).expect("No file found");
+        let file = File::open(input)
+            .with_context(|| format!("Failed opening input file {}", input.display()))?;
 
         //Info for indicatif ProgressBar
         let file_meta = file.metadata()?;
@@ -46,80 +403,141 @@ pub fn initialize_progress_bar(format: &IoFormat, input: &Path) -> Result<Progre
 
 /// The bytes must have start_code_emulation_prevention_3_byte removed
 pub fn st2094_40_sei_msg(sei_payload: &[u8], validate: bool) -> Result<Option<SeiMessage>> {
-    let res = if sei_payload.len() >= 4 {
-        let sei = SeiMessage::parse_sei_rbsp(sei_payload)?;
-
-        sei.into_iter().find(|msg| {
-            if msg.payload_type == USER_DATA_REGISTERED_ITU_T_35 && msg.payload_size >= 7 {
-                let start = msg.payload_offset;
-                let end = start + msg.payload_size;
-
-                let bytes = &sei_payload[start..end];
-                let mut reader = BsIoSliceReader::from_slice(bytes);
-
-                let itu_t_t35_country_code = reader.read::<8, u8>().unwrap();
-                let itu_t_t35_terminal_provider_code = reader.read::<16, u16>().unwrap();
-                let itu_t_t35_terminal_provider_oriented_code = reader.read::<16, u16>().unwrap();
-
-                if itu_t_t35_country_code == 0xB5
-                    && itu_t_t35_terminal_provider_code == 0x003C
-                    && itu_t_t35_terminal_provider_oriented_code == 0x0001
-                {
-                    let application_identifier = reader.read::<8, u8>().unwrap();
-                    let application_version = reader.read::<8, u8>().unwrap();
-
-                    let valid_version = if validate {
-                        application_version == 1
-                    } else {
-                        application_version <= 1
-                    };
-
-                    if application_identifier == 4 && valid_version {
-                        return true;
-                    }
-                }
+    Ok(st2094_40_sei_msgs(sei_payload, validate)?
+        .into_iter()
+        .next())
+}
+
+/// All the HDR10+ messages in the SEI, there should only be one per access unit.
+///
+/// The bytes must have start_code_emulation_prevention_3_byte removed
+pub fn st2094_40_sei_msgs(sei_payload: &[u8], validate: bool) -> Result<Vec<SeiMessage>> {
+    let mut res = Vec::new();
+
+    if sei_payload.len() >= 4 {
+        for msg in SeiMessage::parse_sei_rbsp(sei_payload)? {
+            if is_st2094_40_msg(sei_payload, &msg, validate)? {
+                res.push(msg);
             }
+        }
+    }
 
-            false
-        })
-    } else {
-        None
+    Ok(res)
+}
+
+fn is_st2094_40_msg(sei_payload: &[u8], msg: &SeiMessage, validate: bool) -> Result<bool> {
+    if msg.payload_type != USER_DATA_REGISTERED_ITU_T_35 || msg.payload_size < 7 {
+        return Ok(false);
+    }
+
+    let start = msg.payload_offset;
+    let end = start + msg.payload_size;
+
+    let Some(bytes) = sei_payload.get(start..end) else {
+        bail!(
+            "SEI message payload out of bounds: {start}..{end}, SEI size {}",
+            sei_payload.len()
+        );
     };
+    let mut reader = BsIoSliceReader::from_slice(bytes);
 
-    Ok(res)
+    let itu_t_t35_country_code = reader.read::<8, u8>()?;
+    let itu_t_t35_terminal_provider_code = reader.read::<16, u16>()?;
+    let itu_t_t35_terminal_provider_oriented_code = reader.read::<16, u16>()?;
+
+    if itu_t_t35_country_code == 0xB5
+        && itu_t_t35_terminal_provider_code == 0x003C
+        && itu_t_t35_terminal_provider_oriented_code == 0x0001
+    {
+        let application_identifier = reader.read::<8, u8>()?;
+        let application_version = reader.read::<8, u8>()?;
+
+        let valid_version = if validate {
+            application_version == 1
+        } else {
+            application_version <= 1
+        };
+
+        return Ok(application_identifier == 4 && valid_version);
+    }
+
+    Ok(false)
+}
+
+pub fn is_sei_nal_type(nal_type: u8) -> bool {
+    matches!(nal_type, NAL_SEI_PREFIX | NAL_SEI_SUFFIX)
 }
 
-// Returns Some when the SEI needs to be written
-// Otherwise, the NALU only contains one SEI message, and can be dropped
-pub fn prefix_sei_removed_hdr10plus_nalu(
+/// Finds the HDR10+ SEI messages in a prefix or suffix SEI NAL unit, from any layer.
+/// Dolby Vision enhancement layer NALUs are unwrapped to look for an SEI.
+pub fn hdr10plus_sei_in_nal(
     chunk: &[u8],
     nal: &NALUnit,
-) -> Result<(bool, Option<Vec<u8>>)> {
-    let (st2094_40_msg, payload) = if nal.nal_type == NAL_SEI_PREFIX {
-        let sei_payload = clear_start_code_emulation_prevention_3_byte(&chunk[nal.start..nal.end]);
-        let msg = st2094_40_sei_msg(&sei_payload, false)?;
+    validate: bool,
+) -> Result<Option<Hdr10PlusSei>> {
+    let layer = NalLayer::from_nal(nal);
 
-        (msg, Some(sei_payload))
-    } else {
-        (None, None)
+    let data = match layer {
+        // Skip the NAL_UNSPEC63 header
+        NalLayer::DolbyVisionEl => chunk.get(nal.start + 2..nal.end).unwrap_or_default(),
+        _ => &chunk[nal.start..nal.end],
+    };
+
+    let nal_type = match data.first() {
+        Some(b) if is_sei_nal_type((b >> 1) & 0x3F) => (b >> 1) & 0x3F,
+        _ => return Ok(None),
     };
 
-    let has_st2094_40 = st2094_40_msg.is_some();
+    let sei_payload = clear_start_code_emulation_prevention_3_byte(data);
+    let msgs = st2094_40_sei_msgs(&sei_payload, validate)?;
+
+    if msgs.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(Hdr10PlusSei {
+        layer,
+        suffix: nal_type == NAL_SEI_SUFFIX,
+        msgs,
+        sei_payload,
+    }))
+}
+
+// Works for both prefix and suffix SEI NALUs, all the HDR10+ messages are removed
+// Returns the number of removed messages, and Some when the SEI needs to be written
+// Otherwise, the NALU only contains HDR10+ SEI messages, and can be dropped
+pub fn sei_removed_hdr10plus_nalu(
+    chunk: &[u8],
+    nal: &NALUnit,
+    validate: bool,
+) -> Result<(usize, Option<Vec<u8>>)> {
+    if !is_sei_nal_type(nal.nal_type) {
+        return Ok((0, None));
+    }
+
+    let mut payload = clear_start_code_emulation_prevention_3_byte(&chunk[nal.start..nal.end]);
+    let st2094_40_msgs = st2094_40_sei_msgs(&payload, validate)?;
 
-    if let (Some(msg), Some(mut payload)) = (st2094_40_msg, payload) {
-        let messages = SeiMessage::parse_sei_rbsp(&payload)?;
+    if st2094_40_msgs.is_empty() {
+        return Ok((0, None));
+    }
+
+    let messages = SeiMessage::parse_sei_rbsp(&payload)?;
 
-        // Only remove ST2094-40 message if there are others
-        if messages.len() > 1 {
+    // Only remove ST2094-40 messages if there are others
+    if messages.len() > st2094_40_msgs.len() {
+        // Last first, to keep the offsets valid
+        for msg in st2094_40_msgs.iter().rev() {
             let start = msg.msg_offset;
             let end = msg.payload_offset + msg.payload_size;
 
             payload.drain(start..end);
-            add_start_code_emulation_prevention_3_byte(&mut payload);
-
-            return Ok((true, Some(payload)));
         }
+
+        add_start_code_emulation_prevention_3_byte(&mut payload);
+
+        return Ok((st2094_40_msgs.len(), Some(payload)));
     }
 
-    Ok((has_st2094_40, None))
+    Ok((st2094_40_msgs.len(), None))
 }
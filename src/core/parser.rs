@@ -1,53 +1,156 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs::File;
 use std::io::{BufWriter, Write, stdout};
 use std::path::PathBuf;
 
-use anyhow::{Result, bail, ensure};
-use hevc_parser::utils::{
-    add_start_code_emulation_prevention_3_byte, clear_start_code_emulation_prevention_3_byte,
-};
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use hevc_parser::utils::add_start_code_emulation_prevention_3_byte;
 use indicatif::ProgressBar;
 
 use hevc_parser::HevcParser;
-use hevc_parser::hevc::{Frame, NAL_SEI_PREFIX, NALUnit};
+use hevc_parser::hevc::{NAL_UNSPEC63, NALUnit};
 use hevc_parser::io::{IoFormat, IoProcessor, processor};
 use processor::{HevcProcessor, HevcProcessorOpts};
 
 use hdr10plus::metadata::Hdr10PlusMetadata;
-use hdr10plus::metadata_json::generate_json;
+use hdr10plus::metadata_json::{
+    MetadataJsonWriter, SceneTracker, SerializedFrame, metadata_list_profile,
+};
 
 use crate::CliOptions;
 
-use super::{ParserError, st2094_40_sei_msg};
+use super::metadata_file::combined_profile;
+use super::{
+    NalLayer, ParserError, PayloadSpool, PayloadSpoolReader, PresentationIndex,
+    hdr10plus_sei_in_nal, is_sei_nal_type,
+};
 
 pub const TOOL_NAME: &str = env!("CARGO_PKG_NAME");
 pub const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub struct Parser {
     input: PathBuf,
+    /// JSON output file, the frames are kept for `take_frames` when there is none
     output: Option<PathBuf>,
 
     options: CliOptions,
     progress_bar: ProgressBar,
 
+    /// Metadata of the frames not yet finalized by the HEVC parser, in decode order
     hdr10plus_sei_list: Vec<MetadataFrame>,
     skip_reorder: bool,
 
+    /// Number of metadata frames added, including the ones already finalized
+    metadata_count: usize,
+    /// Number of `HevcParser::ordered_frames` already finalized
+    finalized_frames: usize,
+    /// Decoded index of the first finalized metadata frame without a matching frame
+    orphan_metadata: Option<u64>,
+    /// Last metadata by decode order, used to fill the gaps
+    last_metadata: Option<Vec<u8>>,
+
+    /// Finalized payloads in output order, waiting to be parsed
+    pending_payloads: Vec<Vec<u8>>,
+    /// Parsed payloads, or their serialized JSON frames when writing a JSON file.
+    /// Kept on disk until the JSON is written.
+    spool: Option<PayloadSpool>,
+    /// Last spooled frame, and the scene changes for a profile B list or any other
+    previous_frame: Option<Hdr10PlusMetadata>,
+    scenes: SceneTracker,
+    profile_b_scenes: SceneTracker,
+    /// Profile and version of the parsed payloads
+    profile: Option<&'static str>,
+    version: Option<u8>,
+
+    /// HDR10+ SEI messages found, by layer
+    layer_seis: BTreeMap<NalLayer, LayerSeis>,
+    /// Number of base layer HDR10+ SEI messages found in suffix SEI NALUs
+    suffix_sei_count: usize,
+    /// Decoded index of the frames with more than one HDR10+ SEI message
+    duplicate_frames: Vec<u64>,
+    /// Undecodable payloads skipped in lenient mode
+    skipped_payloads: Vec<SkippedPayload>,
+
     parser_opts: ParserOptions,
 }
 
 #[derive(Debug, Clone)]
 pub struct MetadataFrame {
     pub decoded_index: u64,
-    pub presentation_number: usize,
     pub metadata: Option<Vec<u8>>,
 }
 
 #[derive(Default)]
 pub struct ParserOptions {
     pub limit: Option<u64>,
+    pub report_layers: bool,
+    pub duplicates: DuplicatePolicy,
+    /// Skip undecodable payloads instead of failing
+    pub lenient: bool,
+    /// Number of threads used to parse the payloads, serial when 0 or 1
+    pub threads: usize,
+    /// Don't print the warnings and reports
+    pub quiet: bool,
+}
+
+/// Metadata frames extracted without writing a JSON file, in output order.
+///
+/// The payloads are read back from the temporary spool file in batches.
+pub struct ExtractedFrames {
+    _spool: PayloadSpool,
+    reader: PayloadSpoolReader,
+    frames: VecDeque<Hdr10PlusMetadata>,
+    frame_count: usize,
+
+    profile: &'static str,
+    scenes: SceneTracker,
+    previous: Option<Hdr10PlusMetadata>,
+    scene_first_frames: Vec<usize>,
+}
+
+/// HDR10+ SEI messages found in a layer
+#[derive(Debug, Default)]
+struct LayerSeis {
+    count: usize,
+    /// Decoded index of the frames with a message, only kept for the layer report
+    decoded_frames: Vec<u64>,
+}
+
+/// Spooled frame of the JSON output, with whether it starts a scene
+/// when the list is profile B, or any other profile
+struct SpooledJsonFrame {
+    frame: SerializedFrame,
+    new_scene: bool,
+    new_profile_b_scene: bool,
+}
+
+#[derive(Debug)]
+pub struct SkippedPayload {
+    pub decoded_index: u64,
+    pub error: anyhow::Error,
 }
 
+/// Which metadata to keep when an access unit has more than one HDR10+ SEI message
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Keep the first message, in bitstream order
+    #[default]
+    First,
+    /// Keep the last message, in bitstream order
+    Last,
+    /// Fail the extraction
+    Error,
+}
+
+/// Maximum amount of duplicate frames printed
+const MAX_REPORTED_DUPLICATES: usize = 10;
+
+/// Maximum amount of frame ranges printed for each layer
+const MAX_REPORTED_RANGES: usize = 10;
+
+/// Number of finalized payloads parsed at once
+const PARSE_BATCH_SIZE: usize = 10_000;
+
 impl Parser {
     pub fn new(
         input: PathBuf,
@@ -64,6 +167,21 @@ impl Parser {
             progress_bar,
             hdr10plus_sei_list: Vec::new(),
             skip_reorder,
+            metadata_count: 0,
+            finalized_frames: 0,
+            orphan_metadata: None,
+            last_metadata: None,
+            pending_payloads: Vec::new(),
+            spool: None,
+            previous_frame: None,
+            scenes: SceneTracker::default(),
+            profile_b_scenes: SceneTracker::default(),
+            profile: None,
+            version: None,
+            layer_seis: BTreeMap::new(),
+            suffix_sei_count: 0,
+            duplicate_frames: Vec::new(),
+            skipped_payloads: Vec::new(),
             parser_opts,
         }
     }
@@ -89,39 +207,82 @@ impl Parser {
 
     pub fn add_hdr10plus_sei(&mut self, nals: &[NALUnit], chunk: &[u8]) -> Result<()> {
         for nal in nals {
-            if let NAL_SEI_PREFIX = nal.nal_type {
-                let sei_payload =
-                    clear_start_code_emulation_prevention_3_byte(&chunk[nal.start..nal.end]);
-
-                if let Some(msg) = st2094_40_sei_msg(&sei_payload, self.options.validate)? {
-                    let start = msg.payload_offset;
-                    let end = start + msg.payload_size;
-
-                    // Re-add removed bytes
-                    let mut bytes = sei_payload[start..end].to_vec();
-                    add_start_code_emulation_prevention_3_byte(&mut bytes);
-
-                    self.hdr10plus_sei_list.push(MetadataFrame {
-                        decoded_index: nal.decoded_frame_index,
-                        presentation_number: 0,
-                        metadata: Some(bytes),
-                    });
+            let hdr10plus_sei = if is_sei_nal_type(nal.nal_type) || nal.nal_type == NAL_UNSPEC63 {
+                let res = hdr10plus_sei_in_nal(chunk, nal, self.options.validate);
+
+                match res {
+                    Ok(sei) => sei,
+                    Err(e) if self.parser_opts.lenient => {
+                        if NalLayer::from_nal(nal) == NalLayer::Base {
+                            let e = e.context("Failed parsing SEI NAL unit");
+                            self.skip_payload(nal.decoded_frame_index, e);
+                        }
+
+                        None
+                    }
+                    Err(e) => {
+                        return Err(e).with_context(|| {
+                            format!(
+                                "Failed parsing SEI NAL unit of decoded frame {}",
+                                nal.decoded_frame_index
+                            )
+                        });
+                    }
+                }
+            } else {
+                None
+            };
+
+            if let Some(sei) = hdr10plus_sei {
+                let layer_seis = self.layer_seis.entry(sei.layer).or_default();
+                layer_seis.count += sei.msgs.len();
+
+                if self.parser_opts.report_layers
+                    && layer_seis.decoded_frames.last() != Some(&nal.decoded_frame_index)
+                {
+                    layer_seis.decoded_frames.push(nal.decoded_frame_index);
+                }
+
+                // Only the base layer metadata is extracted
+                if sei.layer == NalLayer::Base {
+                    if sei.suffix {
+                        self.suffix_sei_count += sei.msgs.len();
+                    }
+
+                    for msg in &sei.msgs {
+                        let start = msg.payload_offset;
+                        let end = start + msg.payload_size;
+
+                        if self.parser_opts.lenient {
+                            let res = self.decode_payload(&sei.sei_payload[start..end]);
+
+                            if let Err(e) = res {
+                                self.skip_payload(nal.decoded_frame_index, e);
+                                continue;
+                            }
+                        }
+
+                        // Re-add removed bytes
+                        let mut bytes = sei.sei_payload[start..end].to_vec();
+                        add_start_code_emulation_prevention_3_byte(&mut bytes);
+
+                        self.add_frame_metadata(nal.decoded_frame_index, bytes);
+                    }
                 }
             }
 
             if let Some(last_meta) = self.hdr10plus_sei_list.last() {
                 // Slice and no metadata for this index, means there was nothing in SEI prefix
-                if nal.is_slice() && last_meta.decoded_index < nal.decoded_frame_index {
-                    self.hdr10plus_sei_list.push(MetadataFrame {
-                        decoded_index: nal.decoded_frame_index,
-                        presentation_number: 0,
-                        metadata: None,
-                    });
+                if nal.is_slice()
+                    && nal.nuh_layer_id == 0
+                    && last_meta.decoded_index < nal.decoded_frame_index
+                {
+                    self.push_frame(nal.decoded_frame_index, None);
                 }
             }
         }
 
-        if self.hdr10plus_sei_list.is_empty() {
+        if self.metadata_count == 0 {
             bail!(ParserError::NoMetadataFound);
         } else if self.options.verify {
             bail!(ParserError::MetadataDetected);
@@ -130,109 +291,543 @@ impl Parser {
         Ok(())
     }
 
-    pub fn parse_metadata_list(&self, sei_list: &Vec<&Vec<u8>>) -> Result<Vec<Hdr10PlusMetadata>> {
-        print!("Reading parsed dynamic metadata... ");
-        stdout().flush().ok();
+    fn add_frame_metadata(&mut self, decoded_index: u64, bytes: Vec<u8>) {
+        match self.hdr10plus_sei_list.last_mut() {
+            Some(last_meta) if last_meta.decoded_index == decoded_index => {
+                if last_meta.metadata.is_some() {
+                    if self.duplicate_frames.last() != Some(&decoded_index) {
+                        self.duplicate_frames.push(decoded_index);
+                    }
+
+                    if self.parser_opts.duplicates != DuplicatePolicy::Last {
+                        return;
+                    }
+                }
 
-        let mut complete_metadata = Vec::new();
+                // Suffix SEI, the frame's slices were already added without metadata
+                last_meta.metadata = Some(bytes);
+            }
+            _ => self.push_frame(decoded_index, Some(bytes)),
+        }
+    }
+
+    fn push_frame(&mut self, decoded_index: u64, metadata: Option<Vec<u8>>) {
+        self.hdr10plus_sei_list.push(MetadataFrame {
+            decoded_index,
+            metadata,
+        });
+        self.metadata_count += 1;
+    }
 
-        //Loop over lines and read metadata, HDR10+ LLC format
-        for data in sei_list {
-            let bytes = hevc_parser::utils::clear_start_code_emulation_prevention_3_byte(data);
+    fn decode_payload(&self, bytes: &[u8]) -> Result<()> {
+        let metadata = Hdr10PlusMetadata::parse(bytes).context("Failed parsing HDR10+ metadata")?;
 
-            // Parse metadata
-            let metadata = Hdr10PlusMetadata::parse(&bytes)?;
+        if self.options.validate {
+            metadata.validate().context("Invalid HDR10+ metadata")?;
+        }
 
-            // Validate values
-            if self.options.validate {
-                metadata.validate()?;
+        Ok(())
+    }
+
+    /// The frame is considered as having no metadata, unless another message is valid
+    fn skip_payload(&mut self, decoded_index: u64, error: anyhow::Error) {
+        self.skipped_payloads.push(SkippedPayload {
+            decoded_index,
+            error,
+        });
+
+        let has_frame = self
+            .hdr10plus_sei_list
+            .last()
+            .is_some_and(|last_meta| last_meta.decoded_index == decoded_index);
+
+        if !has_frame {
+            self.push_frame(decoded_index, None);
+        }
+    }
+
+    /// Moves the metadata of the frames finalized by the HEVC parser to the spool, in output order.
+    ///
+    /// The parser finalizes the frames one GOP at a time, so only the current GOP is kept in memory.
+    fn finalize_frames(&mut self, parser: &HevcParser) -> Result<()> {
+        let frames = &parser.ordered_frames()[self.finalized_frames..];
+
+        let Some(last_decoded_index) = frames.iter().map(|f| f.decoded_number).max() else {
+            return Ok(());
+        };
+
+        let count = self
+            .hdr10plus_sei_list
+            .iter()
+            .take_while(|m| m.decoded_index <= last_decoded_index)
+            .count();
+
+        // Leading frames can only be missing metadata when their payloads were skipped
+        if self.last_metadata.is_none() {
+            let first_metadata = self.hdr10plus_sei_list[..count]
+                .iter()
+                .find_map(|m| m.metadata.clone());
+
+            match first_metadata {
+                Some(bytes) => self.last_metadata = Some(bytes),
+                // Wait for the next GOPs
+                None => return Ok(()),
             }
+        }
 
-            complete_metadata.push(metadata);
+        // Same behaviour as FFmpeg
+        // Use metadata from previous SEI by decode order and reorder after
+        let mut finalized = Vec::with_capacity(count);
+        for m in self.hdr10plus_sei_list.drain(..count) {
+            let bytes = match m.metadata {
+                Some(bytes) => {
+                    self.last_metadata = Some(bytes.clone());
+                    bytes
+                }
+                None => self.last_metadata.clone().unwrap_or_default(),
+            };
+
+            finalized.push((m.decoded_index, bytes));
         }
 
-        println!("Done.");
+        if self.skip_reorder {
+            self.pending_payloads
+                .extend(finalized.into_iter().map(|(_, bytes)| bytes));
+        } else {
+            // Reorder to display output order
+            let mut by_decoded_index: HashMap<u64, Vec<u8>> = finalized.into_iter().collect();
+
+            // Frames without metadata are reported by the final frame count check
+            self.pending_payloads.extend(
+                frames
+                    .iter()
+                    .filter_map(|f| by_decoded_index.remove(&f.decoded_number)),
+            );
+
+            if self.orphan_metadata.is_none() {
+                self.orphan_metadata = by_decoded_index.into_keys().min();
+            }
+        }
 
-        Ok(complete_metadata)
+        self.finalized_frames = parser.ordered_frames().len();
+
+        if self.pending_payloads.len() >= PARSE_BATCH_SIZE {
+            self.spool_pending_payloads()?;
+        }
+
+        Ok(())
     }
 
-    fn write_json(&self, metadata: Vec<Hdr10PlusMetadata>) -> Result<()> {
-        match &self.output {
-            Some(path) => {
-                let save_file = File::create(path).expect("Can't create file");
-                let mut writer = BufWriter::with_capacity(10_000_000, save_file);
+    /// Parses and validates the pending payloads, before writing them to the spool.
+    ///
+    /// With a JSON output, the frames are serialized once here and copied to the file at the end.
+    fn spool_pending_payloads(&mut self) -> Result<()> {
+        if self.pending_payloads.is_empty() {
+            return Ok(());
+        }
+
+        let first_frame = self.spool.as_ref().map_or(0, |spool| spool.len());
+        let sei_list: Vec<&Vec<u8>> = self.pending_payloads.iter().collect();
+        let metadata = self.parse_metadata_list(&sei_list, first_frame, self.options.validate)?;
 
-                print!("Generating and writing metadata to JSON file... ");
-                stdout().flush().ok();
+        let list: Vec<&Hdr10PlusMetadata> = metadata.iter().collect();
+        let profile = metadata_list_profile(&list);
 
-                let list: Vec<&Hdr10PlusMetadata> = metadata.iter().collect();
-                let final_json = generate_json(&list, TOOL_NAME, TOOL_VERSION);
+        self.profile = Some(combined_profile(self.profile, profile));
+        self.version = self.version.or(list.first().map(|m| m.application_version));
 
-                writeln!(writer, "{}", serde_json::to_string_pretty(&final_json)?)?;
+        let spool = match &mut self.spool {
+            Some(spool) => spool,
+            None => {
+                let spool = match &self.output {
+                    Some(path) => PayloadSpool::create(path)?,
+                    None => PayloadSpool::create_temp()?,
+                };
 
-                println!("Done.");
+                self.spool.insert(spool)
+            }
+        };
 
-                writer.flush()?;
+        if self.output.is_some() {
+            for m in metadata {
+                let previous = self.previous_frame.as_ref();
+                let new_scene = self
+                    .scenes
+                    .next_frame("N/A", previous, &m)
+                    .scene_frame_index
+                    == 0;
+                let new_profile_b_scene = self
+                    .profile_b_scenes
+                    .next_frame("B", previous, &m)
+                    .scene_frame_index
+                    == 0;
+
+                let frame = SpooledJsonFrame {
+                    frame: SerializedFrame::new(&m)?,
+                    new_scene,
+                    new_profile_b_scene,
+                };
+                spool.push(&frame.to_bytes()?)?;
+
+                self.previous_frame = Some(m);
+            }
+
+            self.pending_payloads.clear();
+        } else {
+            for payload in self.pending_payloads.drain(..) {
+                spool.push(&payload)?;
             }
-            None => bail!("Output path required!"),
         }
 
         Ok(())
     }
 
-    pub fn reorder_metadata(&mut self, frames: &[Frame]) {
-        print!("Reordering metadata... ");
+    /// `first_frame` is the output frame number of the first payload
+    pub fn parse_metadata_list(
+        &self,
+        sei_list: &[&Vec<u8>],
+        first_frame: usize,
+        validate: bool,
+    ) -> Result<Vec<Hdr10PlusMetadata>> {
+        let threads = self.parser_opts.threads.max(1);
+
+        let complete_metadata = if threads > 1 && sei_list.len() > threads {
+            let chunk_size = sei_list.len().div_ceil(threads);
+
+            // Chunks are joined in order, so the first error is also the first by frame
+            let parsed_chunks = std::thread::scope(|s| {
+                let handles: Vec<_> = sei_list
+                    .chunks(chunk_size)
+                    .enumerate()
+                    .map(|(i, chunk)| {
+                        let first_frame = first_frame + i * chunk_size;
+                        s.spawn(move || parse_payloads(chunk, first_frame, validate))
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .map(|handle| {
+                        handle
+                            .join()
+                            .map_err(|_| anyhow!("Metadata parsing thread panicked"))?
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })?;
+
+            parsed_chunks.into_iter().flatten().collect()
+        } else {
+            parse_payloads(sei_list, first_frame, validate)?
+        };
+
+        Ok(complete_metadata)
+    }
+
+    /// The extracted frames, when there is no JSON output
+    pub fn take_frames(&mut self) -> Result<ExtractedFrames> {
+        let Some(mut spool) = self.spool.take().filter(|spool| !spool.is_empty()) else {
+            bail!("Failed reading parsed metadata.");
+        };
+
+        Ok(ExtractedFrames {
+            reader: spool.reader()?,
+            _spool: spool,
+            frames: VecDeque::new(),
+            frame_count: 0,
+            profile: self.profile.unwrap_or("N/A"),
+            scenes: SceneTracker::default(),
+            previous: None,
+            scene_first_frames: Vec::new(),
+        })
+    }
+
+    /// Writes the spooled frames to the JSON file, now that the profile is known
+    fn write_json(&mut self) -> Result<()> {
+        let path = self.output.as_ref().context("Output path required!")?;
+
+        let Some(mut spool) = self.spool.take().filter(|spool| !spool.is_empty()) else {
+            bail!("Failed reading parsed metadata.");
+        };
+        let profile = self.profile.unwrap_or("N/A");
+
+        let save_file = File::create(path)
+            .with_context(|| format!("Failed creating output file {}", path.display()))?;
+        let writer = BufWriter::with_capacity(10_000_000, save_file);
+
+        print!("Generating and writing metadata to JSON file... ");
         stdout().flush().ok();
 
-        self.hdr10plus_sei_list.sort_by_cached_key(|m| {
-            let matching_index = frames
-                .iter()
-                .position(|f| m.decoded_index == f.decoded_number);
+        let version = self.version.unwrap_or(1);
+        let mut json_writer = MetadataJsonWriter::new(writer, profile, version)?;
+        let mut reader = spool.reader()?;
 
-            if let Some(i) = matching_index {
-                frames[i].presentation_number
-            } else {
-                panic!(
-                    "Missing frame/slices for metadata! Decoded index {}",
-                    m.decoded_index
-                );
+        loop {
+            let records = reader.read_batch(PARSE_BATCH_SIZE)?;
+
+            if records.is_empty() {
+                break;
             }
-        });
 
-        self.hdr10plus_sei_list
-            .iter_mut()
-            .enumerate()
-            .for_each(|(idx, m)| {
-                m.presentation_number = idx;
-            });
+            for record in records {
+                let spooled = SpooledJsonFrame::from_bytes(record)?;
+
+                // Bezier curves only delimit the scenes of profile B lists
+                let new_scene = if profile == "B" {
+                    spooled.new_profile_b_scene
+                } else {
+                    spooled.new_scene
+                };
+
+                json_writer.write_serialized_frame(&spooled.frame, new_scene)?;
+            }
+        }
+
+        let mut writer = json_writer.finish(TOOL_NAME, TOOL_VERSION)?;
+        writeln!(writer)?;
+        writer.flush()?;
 
         println!("Done.");
+
+        Ok(())
     }
 
-    pub fn fill_metadata_gaps(&mut self) {
-        print!("Filling metadata gaps... ");
-        stdout().flush().ok();
+    /// The frames of each layer are reported in presentation order, as ranges
+    fn print_layer_report(&self, index: &PresentationIndex) {
+        println!("HDR10+ SEI messages by layer:");
 
-        let present_meta_list: Vec<(usize, Vec<u8>)> = self
-            .hdr10plus_sei_list
+        for (layer, seis) in &self.layer_seis {
+            let mut frames = Vec::with_capacity(seis.decoded_frames.len());
+            let mut unordered_frames = Vec::new();
+
+            for decoded_index in &seis.decoded_frames {
+                match index.presentation_number(*decoded_index) {
+                    Some(number) => frames.push(number),
+                    None => unordered_frames.push(*decoded_index),
+                }
+            }
+            frames.sort_unstable();
+
+            let ranges = frame_ranges(&frames);
+            let mut listed = ranges
+                .iter()
+                .take(MAX_REPORTED_RANGES)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            if ranges.len() > MAX_REPORTED_RANGES {
+                listed.push_str(&format!(" and {} more", ranges.len() - MAX_REPORTED_RANGES));
+            }
+
+            println!("  {layer}: {} messages, frames {listed}", seis.count);
+
+            if !unordered_frames.is_empty() {
+                let decoded = frame_ranges(&unordered_frames).join(", ");
+                println!("    Not in the parsed frames: decoded {decoded}");
+            }
+        }
+    }
+
+    fn report_duplicates(&self, index: &PresentationIndex) -> Result<()> {
+        let count = self.duplicate_frames.len();
+
+        if self.parser_opts.quiet {
+            ensure!(
+                self.parser_opts.duplicates != DuplicatePolicy::Error,
+                "Found {count} frames with duplicate HDR10+ SEI messages"
+            );
+
+            return Ok(());
+        }
+
+        match self.parser_opts.duplicates {
+            DuplicatePolicy::First => println!(
+                "Warning: {count} frames have more than one HDR10+ SEI message, the first one was kept."
+            ),
+            DuplicatePolicy::Last => println!(
+                "Warning: {count} frames have more than one HDR10+ SEI message, the last one was kept."
+            ),
+            DuplicatePolicy::Error => {
+                println!("Frames with more than one HDR10+ SEI message:")
+            }
+        }
+
+        // Reported in presentation order, same as the JSON
+        let mut duplicates: Vec<_> = self
+            .duplicate_frames
             .iter()
-            .enumerate()
-            .filter(|(_, e)| e.metadata.is_some())
-            .map(|(idx, e)| (idx, e.metadata.as_ref().unwrap().clone()))
+            .map(|decoded_index| (index.presentation_number(*decoded_index), *decoded_index))
             .collect();
+        duplicates.sort_unstable();
+
+        for (presentation_number, decoded_index) in duplicates.iter().take(MAX_REPORTED_DUPLICATES)
+        {
+            match presentation_number {
+                Some(number) => println!("  Frame {number} (decoded {decoded_index})"),
+                None => println!("  Decoded frame {decoded_index}"),
+            }
+        }
 
-        for (idx, bytes) in present_meta_list {
-            self.hdr10plus_sei_list
-                .iter_mut()
-                .skip(idx + 1)
-                .take_while(|e| e.metadata.is_none())
-                .for_each(|e| e.metadata = Some(bytes.clone()));
+        if count > MAX_REPORTED_DUPLICATES {
+            println!("  ... and {} more", count - MAX_REPORTED_DUPLICATES);
         }
 
-        println!("Done.");
+        if self.parser_opts.duplicates == DuplicatePolicy::Error {
+            bail!("Found {count} frames with duplicate HDR10+ SEI messages");
+        }
+
+        Ok(())
+    }
+
+    fn report_skipped_payloads(&self, index: &PresentationIndex) {
+        println!(
+            "Warning: {} HDR10+ payloads could not be decoded and were skipped:",
+            self.skipped_payloads.len()
+        );
+
+        for skipped in &self.skipped_payloads {
+            let decoded_index = skipped.decoded_index;
+
+            match index.presentation_number(decoded_index) {
+                Some(number) => println!(
+                    "  Frame {number} (decoded {decoded_index}): {:#}",
+                    skipped.error
+                ),
+                None => println!("  Decoded frame {decoded_index}: {:#}", skipped.error),
+            }
+        }
+    }
+}
+
+impl ExtractedFrames {
+    /// Profile of all the frames
+    pub fn profile(&self) -> &'static str {
+        self.profile
+    }
+
+    /// Number of frames read so far
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// First frame of the scenes read so far, computed the same way as for the JSON
+    pub fn scene_first_frames(&self) -> &[usize] {
+        &self.scene_first_frames
+    }
+
+    pub fn next_frame(&mut self) -> Result<Option<Hdr10PlusMetadata>> {
+        if self.frames.is_empty() {
+            let payloads = self.reader.read_batch(PARSE_BATCH_SIZE)?;
+
+            // Already validated before being spooled
+            let sei_list: Vec<&Vec<u8>> = payloads.iter().collect();
+            self.frames
+                .extend(parse_payloads(&sei_list, self.frame_count, false)?);
+        }
+
+        let Some(metadata) = self.frames.pop_front() else {
+            return Ok(None);
+        };
+
+        let scene = self
+            .scenes
+            .next_frame(self.profile, self.previous.as_ref(), &metadata);
+
+        if scene.scene_frame_index == 0 {
+            self.scene_first_frames.push(scene.sequence_frame_index);
+        }
+
+        self.previous = Some(metadata.clone());
+        self.frame_count += 1;
+
+        Ok(Some(metadata))
     }
 }
 
+impl SpooledJsonFrame {
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let scene_offset = u32::try_from(self.frame.scene_offset)?;
+
+        let mut bytes = Vec::with_capacity(self.frame.text.len() + 6);
+        bytes.push(u8::from(self.new_scene));
+        bytes.push(u8::from(self.new_profile_b_scene));
+        bytes.extend_from_slice(&scene_offset.to_le_bytes());
+        bytes.extend_from_slice(self.frame.text.as_bytes());
+
+        Ok(bytes)
+    }
+
+    fn from_bytes(mut bytes: Vec<u8>) -> Result<Self> {
+        ensure!(bytes.len() >= 6, "Invalid spooled JSON frame");
+
+        let new_scene = bytes[0] != 0;
+        let new_profile_b_scene = bytes[1] != 0;
+        let scene_offset = u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]) as usize;
+        bytes.drain(..6);
+
+        Ok(Self {
+            frame: SerializedFrame {
+                text: String::from_utf8(bytes)?,
+                scene_offset,
+            },
+            new_scene,
+            new_profile_b_scene,
+        })
+    }
+}
+
+/// Consecutive frame numbers as `start-end` ranges, the numbers must be sorted
+fn frame_ranges(frames: &[u64]) -> Vec<String> {
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+
+    for &frame in frames {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 >= frame => *end = frame,
+            _ => ranges.push((frame, frame)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{start}-{end}")
+            }
+        })
+        .collect()
+}
+
+/// Parses the HDR10+ LLC format payloads, `first_frame` is the frame number of the first one
+fn parse_payloads(
+    sei_list: &[&Vec<u8>],
+    first_frame: usize,
+    validate: bool,
+) -> Result<Vec<Hdr10PlusMetadata>> {
+    let mut complete_metadata = Vec::with_capacity(sei_list.len());
+
+    for (i, data) in sei_list.iter().enumerate() {
+        let frame = first_frame + i;
+        let bytes = hevc_parser::utils::clear_start_code_emulation_prevention_3_byte(data);
+
+        // Parse metadata
+        let metadata = Hdr10PlusMetadata::parse(&bytes)
+            .with_context(|| format!("Failed parsing HDR10+ metadata of frame {frame}"))?;
+
+        // Validate values
+        if validate {
+            metadata
+                .validate()
+                .with_context(|| format!("Invalid HDR10+ metadata in frame {frame}"))?;
+        }
+
+        complete_metadata.push(metadata);
+    }
+
+    Ok(complete_metadata)
+}
+
 impl IoProcessor for Parser {
     fn input(&self) -> &PathBuf {
         &self.input
@@ -242,53 +837,78 @@ impl IoProcessor for Parser {
         self.progress_bar.inc(delta);
     }
 
-    fn process_nals(&mut self, _parser: &HevcParser, nals: &[NALUnit], chunk: &[u8]) -> Result<()> {
-        self.add_hdr10plus_sei(nals, chunk)
+    fn process_nals(&mut self, parser: &HevcParser, nals: &[NALUnit], chunk: &[u8]) -> Result<()> {
+        self.add_hdr10plus_sei(nals, chunk)?;
+        self.finalize_frames(parser)
     }
 
     fn finalize(&mut self, parser: &HevcParser) -> Result<()> {
         self.progress_bar.finish_and_clear();
 
-        if self.hdr10plus_sei_list.is_empty() {
+        if self.metadata_count == 0 {
             bail!(ParserError::NoMetadataFound);
         }
 
+        if self.suffix_sei_count > 0 && !self.parser_opts.quiet {
+            println!(
+                "Warning: {} HDR10+ SEI messages found in suffix SEI NAL units, this placement is not conformant.",
+                self.suffix_sei_count
+            );
+        }
+
         let frames = parser.ordered_frames();
+        let index = PresentationIndex::new(frames);
 
-        // Some NALUs may have been added without having parsed the full AU or a slice
-        if self.parser_opts.limit.is_some() {
-            self.hdr10plus_sei_list.truncate(frames.len());
+        if self.parser_opts.report_layers {
+            self.print_layer_report(&index);
         }
 
-        ensure!(self.hdr10plus_sei_list.len() == frames.len());
-
-        let has_metadata_gaps = self.hdr10plus_sei_list.iter().any(|e| e.metadata.is_none());
+        if !self.duplicate_frames.is_empty() {
+            self.report_duplicates(&index)?;
+        }
 
-        // Same behaviour as FFmpeg
-        // Use metadata from previous SEI by decode order and reorder after
-        if has_metadata_gaps {
-            self.fill_metadata_gaps();
+        if !self.skipped_payloads.is_empty() && !self.parser_opts.quiet {
+            self.report_skipped_payloads(&index);
         }
 
-        // Reorder to display output order
-        if !self.skip_reorder {
-            self.reorder_metadata(frames);
+        // The last GOP is finalized when the parser finishes
+        self.finalize_frames(parser)?;
+
+        ensure!(
+            self.last_metadata.is_some(),
+            "No decodable HDR10+ metadata found"
+        );
+
+        // Some NALUs may have been added without having parsed the full AU or a slice
+        if self.parser_opts.limit.is_some() {
+            self.metadata_count -= self.hdr10plus_sei_list.len();
+            self.hdr10plus_sei_list.clear();
         }
 
-        let ordered_sei_list = self
+        ensure!(
+            self.metadata_count == frames.len(),
+            "Metadata count ({}) doesn't match the number of frames ({})",
+            self.metadata_count,
+            frames.len()
+        );
+
+        let orphan_metadata = self
             .hdr10plus_sei_list
-            .iter()
-            .map(|e| e.metadata.as_ref().unwrap())
-            .collect();
-        let final_metadata = self.parse_metadata_list(&ordered_sei_list)?;
+            .first()
+            .map(|m| m.decoded_index)
+            .or(self.orphan_metadata);
+
+        if let Some(decoded_index) = orphan_metadata {
+            bail!("Missing frame/slices for metadata! Decoded index {decoded_index}");
+        }
+
+        self.spool_pending_payloads()?;
 
         // Sucessful parse & no --verify
-        if !final_metadata.is_empty() {
-            self.write_json(final_metadata)?;
+        if self.output.is_some() {
+            self.write_json()
         } else {
-            bail!("Failed reading parsed metadata.");
+            Ok(())
         }
-
-        Ok(())
     }
 }
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Write, stdout};
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use indicatif::ProgressBar;
+
+use hevc_parser::HevcParser;
+use hevc_parser::hevc::NALUnit;
+use hevc_parser::io::{IoFormat, IoProcessor, processor};
+use hevc_parser::utils::clear_start_code_emulation_prevention_3_byte;
+use processor::{HevcProcessor, HevcProcessorOpts};
+
+use super::{initialize_progress_bar, is_sei_nal_type, st2094_40_sei_msgs};
+
+/// Maximum amount of mismatched frames printed
+const MAX_REPORTED_MISMATCHES: usize = 10;
+
+/// Expected T.35 payloads (without emulation prevention), in presentation order.
+/// Read one at a time, as the frames of the file are compared.
+pub type ExpectedPayloads = Box<dyn Iterator<Item = Result<Vec<u8>>>>;
+
+/// Re-parses a written HEVC file and compares the HDR10+ payloads
+/// against the expected ones, in presentation order.
+///
+/// The base layer prefix and suffix SEI NAL units are checked, every access unit must have exactly one payload.
+pub struct OutputVerifier {
+    input: PathBuf,
+    validate: bool,
+    progress_bar: ProgressBar,
+
+    expected: ExpectedPayloads,
+    /// Payloads found in the file, by decoded frame index
+    found: HashMap<u64, Vec<Vec<u8>>>,
+
+    mismatches: Vec<Mismatch>,
+}
+
+#[derive(Debug)]
+pub struct Mismatch {
+    pub presentation_number: usize,
+    pub decoded_number: u64,
+    pub reason: MismatchReason,
+}
+
+#[derive(Debug)]
+pub enum MismatchReason {
+    MissingMetadata,
+    DuplicateMetadata(usize),
+    DifferentPayload,
+    UnexpectedFrame,
+}
+
+impl OutputVerifier {
+    pub fn new(input: PathBuf, expected: ExpectedPayloads, validate: bool) -> Result<Self> {
+        let progress_bar = initialize_progress_bar(&IoFormat::Raw, &input)?;
+
+        Ok(Self {
+            input,
+            validate,
+            progress_bar,
+            expected,
+            found: HashMap::new(),
+            mismatches: Vec::new(),
+        })
+    }
+
+    pub fn verify(mut self) -> Result<()> {
+        println!("Verifying HDR10+ metadata in output file...");
+        stdout().flush().ok();
+
+        self.parse_output()?;
+        self.report()
+    }
+
+    fn parse_output(&mut self) -> Result<()> {
+        let chunk_size = 100_000;
+
+        let mut processor =
+            HevcProcessor::new(IoFormat::Raw, HevcProcessorOpts::default(), chunk_size);
+
+        let file = File::open(&self.input)?;
+        let mut reader = Box::new(BufReader::with_capacity(chunk_size, file));
+
+        processor.process_io(&mut reader, self)
+    }
+
+    fn report(&self) -> Result<()> {
+        if self.mismatches.is_empty() {
+            println!("Output verified: all frames match the expected metadata.");
+            return Ok(());
+        }
+
+        println!("Output verification failed, first mismatches (presentation order):");
+        for m in self.mismatches.iter().take(MAX_REPORTED_MISMATCHES) {
+            println!("  {m}");
+        }
+
+        bail!(
+            "Output verification failed: {} mismatched frames",
+            self.mismatches.len()
+        );
+    }
+}
+
+impl IoProcessor for OutputVerifier {
+    fn input(&self) -> &PathBuf {
+        &self.input
+    }
+
+    fn update_progress(&mut self, delta: u64) {
+        self.progress_bar.inc(delta);
+    }
+
+    fn process_nals(&mut self, _parser: &HevcParser, nals: &[NALUnit], chunk: &[u8]) -> Result<()> {
+        let base_layer_seis = nals
+            .iter()
+            .filter(|nal| is_sei_nal_type(nal.nal_type) && nal.nuh_layer_id == 0);
+
+        for nal in base_layer_seis {
+            let sei_payload =
+                clear_start_code_emulation_prevention_3_byte(&chunk[nal.start..nal.end]);
+
+            for msg in st2094_40_sei_msgs(&sei_payload, self.validate)? {
+                let start = msg.payload_offset;
+                let end = start + msg.payload_size;
+
+                self.found
+                    .entry(nal.decoded_frame_index)
+                    .or_default()
+                    .push(sei_payload[start..end].to_vec());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finalize(&mut self, parser: &HevcParser) -> Result<()> {
+        self.progress_bar.finish_and_clear();
+
+        let mut frames: Vec<_> = parser.ordered_frames().iter().collect();
+        frames.sort_by_key(|f| f.presentation_number);
+
+        for (presentation_number, frame) in frames.into_iter().enumerate() {
+            let found = self
+                .found
+                .get(&frame.decoded_number)
+                .map_or(&[][..], |found| found.as_slice());
+
+            let expected = self.expected.next().transpose()?;
+
+            let reason = match (found, expected) {
+                (_, None) => Some(MismatchReason::UnexpectedFrame),
+                ([], Some(_)) => Some(MismatchReason::MissingMetadata),
+                ([found], Some(expected)) => {
+                    (*found != expected).then_some(MismatchReason::DifferentPayload)
+                }
+                (found, Some(_)) => Some(MismatchReason::DuplicateMetadata(found.len())),
+            };
+
+            if let Some(reason) = reason {
+                self.mismatches.push(Mismatch {
+                    presentation_number,
+                    decoded_number: frame.decoded_number,
+                    reason,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Frame {} (decoded {}): {}",
+            self.presentation_number, self.decoded_number, self.reason
+        )
+    }
+}
+
+impl std::fmt::Display for MismatchReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingMetadata => f.write_str("no HDR10+ SEI found"),
+            Self::DuplicateMetadata(count) => {
+                write!(f, "{count} HDR10+ SEI messages in the access unit")
+            }
+            Self::DifferentPayload => f.write_str("payload differs from the expected metadata"),
+            Self::UnexpectedFrame => f.write_str("no expected metadata for frame"),
+        }
+    }
+}
+
+// Mismatches can't be produced through the CLI, as the injected output is verified
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anyhow::Result;
+    use hdr10plus::metadata::Hdr10PlusMetadataEncOpts;
+
+    use super::{ExpectedPayloads, MismatchReason, OutputVerifier};
+    use crate::core::metadata_file::MetadataReader;
+
+    /// Payloads of the regular metadata, with a different `AverageRGB` for `changed_frame`
+    fn expected_payloads(changed_frame: Option<usize>) -> Result<ExpectedPayloads> {
+        let mut reader =
+            MetadataReader::from_file(Path::new("assets/hevc_tests/regular_metadata.json"))?;
+        let opts = Hdr10PlusMetadataEncOpts::default();
+        let mut frame = 0;
+
+        let expected = std::iter::from_fn(move || {
+            let mut meta = reader.next_frame().transpose()?;
+
+            if changed_frame == Some(frame) {
+                meta = meta.map(|mut meta| {
+                    meta.average_maxrgb += 1;
+                    meta
+                });
+            }
+            frame += 1;
+
+            Some(meta.and_then(|meta| meta.encode_with_opts(&opts)))
+        });
+
+        Ok(Box::new(expected))
+    }
+
+    fn verifier(input: &str, expected: ExpectedPayloads) -> Result<OutputVerifier> {
+        let mut verifier = OutputVerifier::new(input.into(), expected, true)?;
+        verifier.parse_output()?;
+
+        Ok(verifier)
+    }
+
+    #[test]
+    fn different_suffix_payload() -> Result<()> {
+        let verifier = verifier(
+            "assets/hevc_tests/suffix-sei.hevc",
+            expected_payloads(Some(5))?,
+        )?;
+
+        assert_eq!(verifier.mismatches.len(), 1);
+        assert_eq!(
+            verifier.mismatches[0].to_string(),
+            "Frame 5 (decoded 1): payload differs from the expected metadata"
+        );
+
+        let err = verifier.report().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Output verification failed: 1 mismatched frames"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_metadata() -> Result<()> {
+        let verifier = verifier(
+            "assets/hevc_tests/duplicate-sei.hevc",
+            expected_payloads(None)?,
+        )?;
+
+        assert!(!verifier.mismatches.is_empty());
+        assert!(
+            verifier
+                .mismatches
+                .iter()
+                .all(|m| matches!(m.reason, MismatchReason::DuplicateMetadata(2)))
+        );
+        assert!(verifier.report().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail, ensure};
+
+use bitvec_helpers::bitstream_io_reader::BsIoSliceReader;
+use hevc_parser::HevcParser;
+use hevc_parser::hevc::{NAL_SPS, NALUnit};
+use hevc_parser::io::{IoFormat, IoProcessor, format_from_path, processor};
+use hevc_parser::utils::clear_start_code_emulation_prevention_3_byte;
+use processor::{HevcProcessor, HevcProcessorOpts};
+
+/// Reads the frame rate from the VUI timing information of the first SPS of a HEVC file.
+///
+/// Only the first access unit is parsed, the SPS must be in the bitstream.
+pub struct VuiFrameRate {
+    input: PathBuf,
+    /// First SPS NAL unit, with emulation prevention
+    sps: Option<Vec<u8>>,
+}
+
+impl VuiFrameRate {
+    pub fn read(input: &Path) -> Result<f64> {
+        let format = format_from_path(input)?;
+        ensure!(
+            format == IoFormat::Raw,
+            "The frame rate can only be read from a raw HEVC file"
+        );
+
+        let chunk_size = 100_000;
+        let opts = HevcProcessorOpts {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let mut processor = HevcProcessor::new(format, opts, chunk_size);
+
+        let file = File::open(input)?;
+        let mut reader = Box::new(BufReader::with_capacity(chunk_size, file));
+
+        let mut vui = Self {
+            input: input.to_path_buf(),
+            sps: None,
+        };
+        processor.process_io(&mut reader, &mut vui)?;
+
+        let Some(sps) = vui.sps else {
+            bail!("No SPS found in {}", input.display());
+        };
+
+        let sps = clear_start_code_emulation_prevention_3_byte(&sps);
+        sps_frame_rate(&sps)
+            .with_context(|| format!("Failed parsing the SPS of {}", input.display()))?
+            .with_context(|| format!("No VUI timing information in {}", input.display()))
+    }
+}
+
+impl IoProcessor for VuiFrameRate {
+    fn input(&self) -> &PathBuf {
+        &self.input
+    }
+
+    fn update_progress(&mut self, _delta: u64) {}
+
+    fn process_nals(&mut self, _parser: &HevcParser, nals: &[NALUnit], chunk: &[u8]) -> Result<()> {
+        if self.sps.is_none() {
+            self.sps = nals
+                .iter()
+                .find(|nal| nal.nal_type == NAL_SPS && nal.nuh_layer_id == 0)
+                .map(|nal| chunk[nal.start..nal.end].to_vec());
+        }
+
+        Ok(())
+    }
+
+    fn finalize(&mut self, _parser: &HevcParser) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Frame rate of the VUI timing information, the SPS being parsed up to it.
+/// Same syntax as `hevc_parser`, which doesn't expose the VUI.
+fn sps_frame_rate(sps: &[u8]) -> Result<Option<f64>> {
+    let mut bs = BsIoSliceReader::from_slice(sps);
+
+    bs.skip_n(16)?; // NAL unit header
+    bs.skip_n(4)?; // sps_video_parameter_set_id
+    let max_sub_layers = bs.read::<3, u8>()? + 1;
+    bs.skip_n(1)?; // sps_temporal_id_nesting_flag
+
+    skip_profile_tier_level(&mut bs, max_sub_layers)?;
+
+    bs.read_ue()?; // sps_seq_parameter_set_id
+    if bs.read_ue()? == 3 {
+        bs.skip_n(1)?; // separate_colour_plane_flag
+    }
+
+    bs.read_ue()?; // pic_width_in_luma_samples
+    bs.read_ue()?; // pic_height_in_luma_samples
+    if bs.read_bit()? {
+        // Conformance window offsets
+        for _ in 0..4 {
+            bs.read_ue()?;
+        }
+    }
+
+    bs.read_ue()?; // bit_depth_luma_minus8
+    bs.read_ue()?; // bit_depth_chroma_minus8
+    let log2_max_poc_lsb = bs.read_ue()? + 4;
+
+    let sub_layer_ordering_info = bs.read_bit()?;
+    let ordered_sub_layers = if sub_layer_ordering_info {
+        max_sub_layers
+    } else {
+        1
+    };
+    for _ in 0..ordered_sub_layers {
+        bs.read_ue()?; // sps_max_dec_pic_buffering_minus1
+        bs.read_ue()?; // sps_max_num_reorder_pics
+        bs.read_ue()?; // sps_max_latency_increase_plus1
+    }
+
+    // Coding and transform block sizes, transform hierarchy depths
+    for _ in 0..6 {
+        bs.read_ue()?;
+    }
+
+    if bs.read_bit()? && bs.read_bit()? {
+        skip_scaling_list_data(&mut bs)?;
+    }
+
+    bs.skip_n(2)?; // amp_enabled_flag, sample_adaptive_offset_enabled_flag
+    if bs.read_bit()? {
+        bs.skip_n(8)?; // pcm_sample_bit_depth_luma_minus1, pcm_sample_bit_depth_chroma_minus1
+        bs.read_ue()?; // log2_min_pcm_luma_coding_block_size_minus3
+        bs.read_ue()?; // log2_diff_max_min_pcm_luma_coding_block_size
+        bs.skip_n(1)?; // pcm_loop_filter_disabled_flag
+    }
+
+    skip_short_term_ref_pic_sets(&mut bs)?;
+
+    if bs.read_bit()? {
+        let num_long_term_ref_pics = bs.read_ue()?;
+
+        for _ in 0..num_long_term_ref_pics {
+            bs.skip_n(log2_max_poc_lsb as u32)?; // lt_ref_pic_poc_lsb_sps
+            bs.skip_n(1)?; // used_by_curr_pic_lt_sps_flag
+        }
+    }
+
+    bs.skip_n(2)?; // sps_temporal_mvp_enabled_flag, strong_intra_smoothing_enabled_flag
+
+    if !bs.read_bit()? {
+        return Ok(None);
+    }
+
+    vui_frame_rate(&mut bs)
+}
+
+fn vui_frame_rate(bs: &mut BsIoSliceReader) -> Result<Option<f64>> {
+    if bs.read_bit()? && bs.read::<8, u8>()? == 255 {
+        bs.skip_n(32)?; // sar_width, sar_height
+    }
+
+    if bs.read_bit()? {
+        bs.skip_n(1)?; // overscan_appropriate_flag
+    }
+
+    if bs.read_bit()? {
+        bs.skip_n(4)?; // video_format, video_full_range_flag
+
+        if bs.read_bit()? {
+            bs.skip_n(24)?; // colour_primaries, transfer_characteristics, matrix_coeffs
+        }
+    }
+
+    if bs.read_bit()? {
+        bs.read_ue()?; // chroma_sample_loc_type_top_field
+        bs.read_ue()?; // chroma_sample_loc_type_bottom_field
+    }
+
+    // neutral_chroma_indication_flag, field_seq_flag, frame_field_info_present_flag
+    bs.skip_n(3)?;
+
+    if bs.read_bit()? {
+        // Default display window offsets
+        for _ in 0..4 {
+            bs.read_ue()?;
+        }
+    }
+
+    if !bs.read_bit()? {
+        return Ok(None);
+    }
+
+    let num_units_in_tick = bs.read::<32, u32>()?;
+    let time_scale = bs.read::<32, u32>()?;
+    ensure!(
+        num_units_in_tick > 0 && time_scale > 0,
+        "Invalid VUI timing information {time_scale}/{num_units_in_tick}"
+    );
+
+    Ok(Some(f64::from(time_scale) / f64::from(num_units_in_tick)))
+}
+
+fn skip_profile_tier_level(bs: &mut BsIoSliceReader, max_sub_layers: u8) -> Result<()> {
+    // General profile, tier and level
+    bs.skip_n(88)?;
+    bs.skip_n(8)?;
+
+    let max_sub_layers_minus1 = max_sub_layers as usize - 1;
+    let mut sub_layers_present = Vec::with_capacity(max_sub_layers_minus1);
+
+    for _ in 0..max_sub_layers_minus1 {
+        let profile_present = bs.read_bit()?;
+        let level_present = bs.read_bit()?;
+
+        sub_layers_present.push((profile_present, level_present));
+    }
+
+    if max_sub_layers_minus1 > 0 {
+        for _ in max_sub_layers_minus1..8 {
+            bs.skip_n(2)?; // reserved_zero_2bits
+        }
+    }
+
+    for (profile_present, level_present) in sub_layers_present {
+        if profile_present {
+            bs.skip_n(88)?;
+        }
+
+        if level_present {
+            bs.skip_n(8)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn skip_scaling_list_data(bs: &mut BsIoSliceReader) -> Result<()> {
+    for size_id in 0..4 {
+        let matrix_count = if size_id == 3 { 2 } else { 6 };
+
+        for _ in 0..matrix_count {
+            if !bs.read_bit()? {
+                bs.read_ue()?; // scaling_list_pred_matrix_id_delta
+                continue;
+            }
+
+            if size_id > 1 {
+                bs.read_se()?; // scaling_list_dc_coef_minus8
+            }
+
+            let coef_count = 64.min(1 << (4 + (size_id << 1)));
+            for _ in 0..coef_count {
+                bs.read_se()?; // scaling_list_delta_coef
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn skip_short_term_ref_pic_sets(bs: &mut BsIoSliceReader) -> Result<()> {
+    let num_short_term_ref_pic_sets = bs.read_ue()? as usize;
+
+    // Number of delta POCs of each set, needed by the predicted sets
+    let mut num_delta_pocs: Vec<usize> = Vec::with_capacity(num_short_term_ref_pic_sets);
+
+    for idx in 0..num_short_term_ref_pic_sets {
+        let inter_ref_pic_set_prediction = idx > 0 && bs.read_bit()?;
+
+        if inter_ref_pic_set_prediction {
+            bs.skip_n(1)?; // delta_rps_sign
+            bs.read_ue()?; // abs_delta_rps_minus1
+
+            // delta_idx_minus1 is only present in slice headers
+            let ref_delta_pocs = num_delta_pocs[idx - 1];
+            let mut count = 0;
+
+            for _ in 0..=ref_delta_pocs {
+                let used_by_curr_pic = bs.read_bit()?;
+                let use_delta = used_by_curr_pic || bs.read_bit()?;
+
+                if use_delta {
+                    count += 1;
+                }
+            }
+
+            num_delta_pocs.push(count);
+        } else {
+            let num_negative_pics = bs.read_ue()? as usize;
+            let num_positive_pics = bs.read_ue()? as usize;
+
+            for _ in 0..num_negative_pics + num_positive_pics {
+                bs.read_ue()?; // delta_poc_minus1
+                bs.skip_n(1)?; // used_by_curr_pic_flag
+            }
+
+            num_delta_pocs.push(num_negative_pics + num_positive_pics);
+        }
+    }
+
+    Ok(())
+}
@@ -6,11 +6,17 @@ mod core;
 mod utils;
 
 use commands::Command;
+use commands::convert::Converter;
+use commands::cut::Cutter;
+use commands::diff::Differ;
 use commands::editor::Editor;
 use commands::extract::Extractor;
+use commands::info::Info;
 use commands::inject::Injector;
+use commands::merge::Merger;
 use commands::plot::Plotter;
 use commands::remove::Remover;
+use commands::split::Splitter;
 
 use crate::core::ParserError;
 
@@ -27,7 +33,7 @@ struct Opt {
     cmd: Command,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct CliOptions {
     pub verify: bool,
     pub validate: bool,
@@ -47,6 +53,12 @@ fn main() -> Result<()> {
         Command::Remove(args) => Remover::remove_sei(args, cli_options),
         Command::Plot(args) => Plotter::plot(args),
         Command::Editor(args) => Editor::edit(args),
+        Command::Convert(args) => Converter::convert(args, cli_options),
+        Command::Info(args) => Info::info(args, cli_options),
+        Command::Diff(args) => Differ::diff(args, cli_options),
+        Command::Merge(args) => Merger::merge(args),
+        Command::Split(args) => Splitter::split(args),
+        Command::Cut(args) => Cutter::cut(args, cli_options),
     };
 
     let actually_errored = if let Err(e) = &res {
@@ -1,3 +1,5 @@
+use anyhow::{Context, Result, ensure};
+
 pub const ST2084_Y_MAX: f64 = 10000.0;
 pub const ST2084_M1: f64 = 2610.0 / 16384.0;
 pub const ST2084_M2: f64 = (2523.0 / 4096.0) * 128.0;
@@ -26,3 +28,101 @@ pub fn nits_to_pq(nits: f64) -> f64 {
     ((ST2084_C1 + ST2084_C2 * y.powf(ST2084_M1)) / (1.0 + ST2084_C3 * y.powf(ST2084_M1)))
         .powf(ST2084_M2)
 }
+
+/// Frame rate as a decimal number or a fraction, like `23.976` or `24000/1001`
+pub fn parse_frame_rate(value: &str) -> Result<f64> {
+    let fps = match value.split_once('/') {
+        Some((num, den)) => {
+            let num: f64 = num.trim().parse()?;
+            let den: f64 = den.trim().parse()?;
+
+            num / den
+        }
+        None => value.trim().parse()?,
+    };
+
+    ensure!(fps.is_finite() && fps > 0.0, "Invalid frame rate {value}");
+
+    Ok(fps)
+}
+
+/// Timestamp formatted as `HH:MM:SS.fraction`, in seconds
+pub fn parse_timestamp(value: &str) -> Result<f64> {
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    ensure!(
+        parts.len() == 3,
+        "Invalid timestamp {value}, expected HH:MM:SS.mmm"
+    );
+
+    let hours: u64 = parts[0]
+        .parse()
+        .with_context(|| format!("Invalid timestamp {value}"))?;
+    let minutes: u64 = parts[1]
+        .parse()
+        .with_context(|| format!("Invalid timestamp {value}"))?;
+    let seconds: f64 = parts[2]
+        .parse()
+        .with_context(|| format!("Invalid timestamp {value}"))?;
+
+    ensure!(
+        minutes < 60 && (0.0..60.0).contains(&seconds),
+        "Invalid timestamp {value}"
+    );
+
+    Ok((hours * 3600 + minutes * 60) as f64 + seconds)
+}
+
+/// SMPTE timecode formatted as `HH:MM:SS:FF`, or `HH:MM:SS;FF` for drop-frame, in frames.
+///
+/// Drop-frame timecodes skip the first frame numbers of every minute except every tenth minute,
+/// they are only defined for the 29.97 and 59.94 frame rates.
+pub fn parse_timecode(value: &str, fps: f64) -> Result<usize> {
+    let value = value.trim();
+    let drop_frame = value.contains(';');
+
+    let parts = value
+        .split([':', ';'])
+        .map(|part| part.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid timecode {value}"))?;
+    ensure!(
+        parts.len() == 4,
+        "Invalid timecode {value}, expected HH:MM:SS:FF"
+    );
+
+    let (hours, minutes, seconds, frames) = (parts[0], parts[1], parts[2], parts[3]);
+
+    // Timecodes count frames at the nominal frame rate, 24 for 23.976
+    let timebase = fps.round() as u64;
+    ensure!(
+        timebase > 0 && minutes < 60 && seconds < 60 && frames < timebase,
+        "Invalid timecode {value} at {fps} fps"
+    );
+
+    let total_minutes = hours * 60 + minutes;
+    let mut frame = (total_minutes * 60 + seconds) * timebase + frames;
+
+    if drop_frame {
+        ensure!(
+            timebase % 30 == 0 && (fps - timebase as f64).abs() > 0.01,
+            "Invalid drop-frame timecode {value}, the frame rate must be 29.97 or 59.94"
+        );
+
+        let dropped = timebase / 15;
+        ensure!(
+            seconds != 0 || frames >= dropped || minutes % 10 == 0,
+            "Invalid drop-frame timecode {value}, the frame number is skipped"
+        );
+
+        frame -= dropped * (total_minutes - total_minutes / 10);
+    }
+
+    Ok(frame as usize)
+}
+
+/// Frame displayed at the timestamp, in seconds.
+///
+/// A frame starting at the timestamp is displayed, despite the rounding errors of the frame rate.
+pub fn timestamp_to_frame(seconds: f64, fps: f64) -> usize {
+    (seconds * fps + 1e-6).floor() as usize
+}
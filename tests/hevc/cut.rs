@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::cargo;
+use assert_fs::prelude::*;
+use hdr10plus::metadata_json::MetadataJsonRoot;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "cut";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "hdr10plus_tool cut [OPTIONS] --range <RANGE> [input_pos]",
+        ));
+    Ok(())
+}
+
+#[test]
+fn whole_stream() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+    let expected_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let output_file = temp.child("cut.hevc");
+    let output_json = temp.child("cut.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--range")
+        .arg("0-258")
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Warning").not());
+
+    output_file.assert(predicate::path::eq_file(input_file));
+    output_json.assert(predicate::path::eq_file(expected_json));
+
+    Ok(())
+}
+
+#[test]
+fn previous_irap() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // Second IDR frame at 259
+    let input = std::fs::read("assets/hevc_tests/regular_start_code_4.hevc")?;
+    let input_file = temp.child("double.hevc");
+    input_file.write_binary(&[input.as_slice(), input.as_slice()].concat())?;
+
+    let output_file = temp.child("cut.hevc");
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_file.as_ref())
+        .arg("--range")
+        .arg("300-400")
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--json-output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: frame 300 is not an IRAP, the cut starts at the previous IRAP frame 259",
+        ))
+        .stdout(predicate::str::contains(
+            "Writing frames 259-402 (144 frames)",
+        ));
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(output_json.as_ref())?)?;
+    let expected_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open("assets/hevc_tests/regular_metadata.json")?)?;
+
+    assert_eq!(metadata_json.scene_info.len(), 144);
+    assert_eq!(
+        metadata_json.scene_info[0].luminance_parameters.average_rgb,
+        expected_json.scene_info[0].luminance_parameters.average_rgb
+    );
+    assert_eq!(
+        metadata_json.scene_info[143]
+            .luminance_parameters
+            .average_rgb,
+        expected_json.scene_info[143]
+            .luminance_parameters
+            .average_rgb
+    );
+
+    Ok(())
+}
+
+#[test]
+fn invalid_range() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular.hevc");
+    let output_file = temp.child("cut.hevc");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--range")
+        .arg("10-300")
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "Invalid range 10-300, the video has 259 frames",
+    ));
+
+    Ok(())
+}
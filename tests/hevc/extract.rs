@@ -7,7 +7,7 @@ use predicates::prelude::*;
 
 use hdr10plus::{
     metadata::{DistributionMaxRgb, Hdr10PlusMetadata},
-    metadata_json::MetadataJsonRoot,
+    metadata_json::{MetadataJsonRoot, generate_json},
 };
 
 const SUBCOMMAND: &str = "extract";
@@ -2024,3 +2024,421 @@ fn regular_mkv() -> Result<()> {
 
     Ok(())
 }
+
+// Same SEIs as `regular.hevc`, duplicated in layer 1 and in Dolby Vision EL NALUs
+// Only the base layer is extracted
+#[test]
+fn layered() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/layered.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+    let expected_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--report-layers")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Base layer: 259"))
+        .stdout(predicate::str::contains("Layer 1: 259"))
+        .stdout(predicate::str::contains(
+            "Dolby Vision enhancement layer (NAL_UNSPEC63): 259",
+        ));
+
+    output_json
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_json));
+
+    Ok(())
+}
+
+// HDR10+ in layer 1 for the first 100 decoded frames, then in the Dolby Vision enhancement layer.
+// Both also have enhancement layer slices.
+#[test]
+fn layered_report() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/layered-el.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+    let expected_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--report-layers")
+        .assert();
+
+    // The frames are in presentation order
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Base layer: 259 messages, frames 0-258",
+        ))
+        .stdout(predicate::str::contains(
+            "Layer 1: 100 messages, frames 0-98, 103",
+        ))
+        .stdout(predicate::str::contains(
+            "Dolby Vision enhancement layer (NAL_UNSPEC63): 159 messages, frames 99-102, 104-258",
+        ));
+
+    output_json
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_json));
+
+    Ok(())
+}
+
+#[test]
+fn suffix_sei() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/suffix-sei.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+    let expected_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: 259 HDR10+ SEI messages found in suffix SEI NAL units",
+        ));
+
+    output_json
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_json));
+
+    Ok(())
+}
+
+#[test]
+fn duplicates_first() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/duplicate-sei.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+    let expected_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: 6 frames have more than one HDR10+ SEI message, the first one was kept.",
+        ))
+        .stdout(predicate::str::contains("Frame 5 (decoded 1)"));
+
+    output_json
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_json));
+
+    Ok(())
+}
+
+#[test]
+fn duplicates_last() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/duplicate-sei.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+    let regular_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--duplicates")
+        .arg("last")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: 6 frames have more than one HDR10+ SEI message, the last one was kept.",
+        ));
+
+    let metadata_root = MetadataJsonRoot::from_file(output_json.as_ref())?;
+    let regular_root = MetadataJsonRoot::from_file(regular_json)?;
+
+    // The first 6 frames all had the same duplicated metadata last
+    let duplicated = &metadata_root.scene_info[0].luminance_parameters;
+    assert_ne!(duplicated, &regular_root.scene_info[0].luminance_parameters);
+
+    for (frame, regular_frame) in metadata_root
+        .scene_info
+        .iter()
+        .zip(regular_root.scene_info.iter())
+    {
+        if frame.sequence_frame_index < 6 {
+            assert_eq!(&frame.luminance_parameters, duplicated);
+        } else {
+            assert_eq!(
+                frame.luminance_parameters,
+                regular_frame.luminance_parameters
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn duplicates_error() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/duplicate-sei.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--duplicates")
+        .arg("error")
+        .assert();
+
+    assert
+        .failure()
+        .stdout(predicate::str::contains("Frame 0 (decoded 0)"))
+        .stderr(predicate::str::contains(
+            "Found 6 frames with duplicate HDR10+ SEI messages",
+        ));
+
+    output_json.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn corrupted_sei() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/corrupted-sei.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "Failed parsing SEI NAL unit of decoded frame 3",
+    ));
+
+    output_json.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn corrupted_sei_lenient() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/corrupted-sei.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+    let regular_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--lenient")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: 2 HDR10+ payloads could not be decoded and were skipped:",
+        ))
+        .stdout(predicate::str::contains(
+            "Frame 1 (decoded 3): Failed parsing SEI NAL unit",
+        ))
+        .stdout(predicate::str::contains(
+            "Frame 10 (decoded 6): Failed parsing HDR10+ metadata",
+        ));
+
+    let metadata_root = MetadataJsonRoot::from_file(output_json.as_ref())?;
+    let regular_root = MetadataJsonRoot::from_file(regular_json)?;
+
+    assert_eq!(metadata_root.scene_info.len(), 259);
+
+    // Only the skipped frames differ, they reuse the previous metadata in decode order
+    let differing_frames: Vec<usize> = metadata_root
+        .scene_info
+        .iter()
+        .zip(regular_root.scene_info.iter())
+        .enumerate()
+        .filter(|(_, (a, b))| a.luminance_parameters != b.luminance_parameters)
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(differing_frames, vec![1, 10]);
+
+    Ok(())
+}
+
+#[test]
+fn threads() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/regular.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+    let expected_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--threads")
+        .arg("4")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_json
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_json));
+
+    Ok(())
+}
+
+#[test]
+fn streamed_json() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/dhdr10-opt.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    // Same as serializing the whole list at once
+    let metadata_root = MetadataJsonRoot::from_file(output_json.as_ref())?;
+    let metadata_list = metadata_root
+        .scene_info
+        .iter()
+        .map(Hdr10PlusMetadata::try_from)
+        .collect::<Result<Vec<_>>>()?;
+    let list: Vec<&Hdr10PlusMetadata> = metadata_list.iter().collect();
+
+    let tool_info = &metadata_root.tool_info;
+    let expected = generate_json(&list, &tool_info.tool, &tool_info.version);
+
+    output_json.assert(format!("{}\n", serde_json::to_string_pretty(&expected)?));
+
+    // The temporary payloads file is removed
+    assert_eq!(dir_file_names(temp.path())?, ["metadata.json"]);
+
+    Ok(())
+}
+
+#[test]
+fn streamed_json_error() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/dhdr10-opt.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    // The JSON file can't be created once the frames are spooled
+    let output_json = temp.child("metadata.json");
+    output_json.create_dir_all()?;
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert
+        .failure()
+        .stderr(predicate::str::contains("Failed creating output file"));
+
+    assert_eq!(dir_file_names(temp.path())?, ["metadata.json"]);
+
+    Ok(())
+}
+
+#[test]
+fn streamed_json_existing_part_file() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let input_file = Path::new("assets/hevc_tests/dhdr10-opt.hevc");
+    let temp = assert_fs::TempDir::new()?;
+
+    let output_json = temp.child("metadata.json");
+
+    // A file of the user, not overwritten nor removed by the temporary payloads file
+    let part_file = temp.child("metadata.json.part");
+    part_file.write_str("user data")?;
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_json.assert(predicate::path::is_file());
+    part_file.assert("user data");
+    assert_eq!(
+        dir_file_names(temp.path())?,
+        ["metadata.json", "metadata.json.part"]
+    );
+
+    Ok(())
+}
+
+fn dir_file_names(dir: &Path) -> Result<Vec<String>> {
+    let mut names = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+
+    Ok(names)
+}
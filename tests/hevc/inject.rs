@@ -142,6 +142,73 @@ fn inject_replace() -> Result<()> {
     Ok(())
 }
 
+// The last metadata frame is duplicated, the count is only known once the JSON was read
+#[test]
+fn shorter_metadata() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let input_json = Path::new("assets/hevc_tests/single-frame-metadata.json");
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--json")
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--verify-output")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: mismatched lengths. video 259, HDR10+ JSON 1",
+        ))
+        .stdout(predicate::str::contains(
+            "Metadata was duplicated at the end to match video length",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn longer_metadata() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/single-frame.hevc");
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--json")
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--verify-output")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: mismatched lengths. video 1, HDR10+ JSON 259",
+        ))
+        .stdout(predicate::str::contains(
+            "Metadata was skipped at the end to match video length",
+        ));
+
+    Ok(())
+}
+
 // Tests that injecting removes existing HDR10+ in the middle of SEI messages
 // And places it back outside as an independent SEI NALU
 #[test]
@@ -189,3 +256,407 @@ fn inject_replace_multimsg() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn verify_output() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--json")
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--verify-output")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Output verified: all frames match the expected metadata.",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn dry_run() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/multimsg-sei.hevc");
+    let input_json = Path::new("assets/hevc_tests/single-frame-metadata.json");
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--json")
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--dry-run")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "1 sharing a NAL with other SEI messages",
+        ))
+        .stdout(predicate::str::contains("NALs added: 1"))
+        .stdout(predicate::str::contains("NALs rewritten: 1"))
+        .stdout(predicate::str::contains(
+            "Projected output size: 248205 bytes",
+        ));
+
+    output_file.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+// Removing and injecting back while keeping the start codes must give the original file
+#[test]
+fn preserve_start_codes() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular.hevc");
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let removed_file = temp.child("hdr10plus_removed_output.hevc");
+    let output_file = temp.child("injected_output.hevc");
+
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let assert = cmd
+        .arg("remove")
+        .arg(input_file)
+        .arg("--output")
+        .arg(removed_file.as_ref())
+        .arg("--preserve-start-codes")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(removed_file.as_ref())
+        .arg("--json")
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--preserve-start-codes")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_file
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(input_file));
+
+    Ok(())
+}
+
+// Tests that the HDR10+ SEI message is appended to the existing prefix SEI NALU
+#[test]
+fn merge_prefix_sei() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/multimsg-sei.hevc");
+    let input_json = Path::new("assets/hevc_tests/single-frame-metadata.json");
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--json")
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--merge-prefix-sei")
+        .arg("--verify-output")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    // Same messages, only the HDR10+ message has moved to the end of the SEI NALU
+    let input_len = std::fs::metadata(input_file)?.len();
+    output_file.assert(predicate::path::is_file());
+    assert_eq!(std::fs::metadata(output_file.as_ref())?.len(), input_len);
+
+    let output_json = temp.child("metadata.json");
+
+    let mut cmd = cargo::cargo_bin_cmd!();
+
+    let assert = cmd
+        .arg("extract")
+        .arg(output_file.as_ref())
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_json
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(input_json));
+
+    Ok(())
+}
+
+/// NAL type, nuh_layer_id and TemporalId of every NAL unit in the bitstream
+fn nal_header_ids(data: &[u8]) -> Vec<(u8, u8, u8)> {
+    data.windows(3)
+        .enumerate()
+        .filter(|(_, w)| w == &[0, 0, 1])
+        .filter_map(|(i, _)| data.get(i + 3..i + 5))
+        .map(|header| {
+            (
+                (header[0] >> 1) & 0x3F,
+                ((header[0] & 0x01) << 5) | (header[1] >> 3),
+                (header[1] & 0x07) - 1,
+            )
+        })
+        .collect()
+}
+
+// Tests that the inserted SEI NALUs have the same TemporalId as their frame
+#[test]
+fn temporal_id() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    // Move the non-reference frames (TRAIL_N) to temporal sub-layer 1
+    let mut data = std::fs::read("assets/hevc_tests/regular_bl_start_code_4.hevc")?;
+    let offsets: Vec<usize> = data
+        .windows(3)
+        .enumerate()
+        .filter(|(_, w)| w == &[0, 0, 1])
+        .map(|(i, _)| i + 3)
+        .collect();
+    for i in offsets {
+        if (data[i] >> 1) & 0x3F == 0 {
+            data[i + 1] = (data[i + 1] & !0x07) | 2;
+        }
+    }
+
+    let input_file = temp.child("temporal_layers.hevc");
+    input_file.write_binary(&data)?;
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file.as_ref())
+        .arg("--json")
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let nals = nal_header_ids(&std::fs::read(output_file.as_ref())?);
+    let sei_before_slices: Vec<_> = nals
+        .windows(2)
+        .filter(|w| w[0].0 == 39 && w[1].0 < 32)
+        .collect();
+
+    assert_eq!(sei_before_slices.len(), 259);
+    assert!(sei_before_slices.iter().any(|w| w[1].2 == 1));
+    assert!(sei_before_slices.iter().all(|w| w[0].2 == w[1].2));
+
+    Ok(())
+}
+
+// Tests that enhancement layer and Dolby Vision NALUs are kept in place
+#[test]
+fn layered() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/layered.hevc");
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--json")
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--verify-output")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    // Only the base layer SEIs are replaced
+    let is_kept = |ids: &(u8, u8, u8)| ids.0 != 39 || ids.1 != 0;
+    let input_nals: Vec<_> = nal_header_ids(&std::fs::read(input_file)?)
+        .into_iter()
+        .filter(is_kept)
+        .collect();
+    let output_nals: Vec<_> = nal_header_ids(&std::fs::read(output_file.as_ref())?)
+        .into_iter()
+        .filter(is_kept)
+        .collect();
+
+    assert_eq!(input_nals, output_nals);
+
+    let output_json = temp.child("metadata.json");
+
+    let mut cmd = cargo::cargo_bin_cmd!();
+
+    let assert = cmd
+        .arg("extract")
+        .arg(output_file.as_ref())
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_json
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(input_json));
+
+    Ok(())
+}
+
+// Tests that the SEIs are only inserted before the base layer slices, with enhancement layer slices
+#[test]
+fn layered_enhancement_layer() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/layered-el.hevc");
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--json")
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--verify-output")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let is_kept = |ids: &(u8, u8, u8)| ids.0 != 39 || ids.1 != 0;
+    let input_nals: Vec<_> = nal_header_ids(&std::fs::read(input_file)?)
+        .into_iter()
+        .filter(is_kept)
+        .collect();
+    let output_nals = nal_header_ids(&std::fs::read(output_file.as_ref())?);
+
+    // Enhancement layer, RPU and Dolby Vision NALUs are kept in order
+    let kept_nals: Vec<_> = output_nals.iter().copied().filter(is_kept).collect();
+    assert_eq!(input_nals, kept_nals);
+
+    // One SEI right before each base layer slice, none before the layer 1 slices
+    let before_slices: Vec<_> = output_nals.windows(2).filter(|w| w[1].0 < 32).collect();
+    let (base, enhancement): (Vec<_>, Vec<_>) = before_slices
+        .into_iter()
+        .partition(|w: &&[(u8, u8, u8)]| w[1].1 == 0);
+
+    assert_eq!(base.len(), 259);
+    assert!(base.iter().all(|w| w[0].0 == 39 && w[0].1 == 0));
+    assert_eq!(enhancement.len(), 259);
+    assert!(enhancement.iter().all(|w| w[0].0 < 32 && w[0].1 == 0));
+
+    Ok(())
+}
+
+#[test]
+fn inject_replace_suffix_sei() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/suffix-sei.hevc");
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let output_file = temp.child("injected_output.hevc");
+    let expected_injected = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--json")
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: Input file already has HDR10+ SEIs, they will be replaced.",
+        ));
+
+    output_file
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_injected));
+
+    Ok(())
+}
+
+#[test]
+fn json_keys_order() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let expected_injected = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+
+    // Compact JSON, with SceneInfo after the summary and JSONInfo last
+    let original: serde_json::Map<String, serde_json::Value> = serde_json::from_str(
+        &std::fs::read_to_string("assets/hevc_tests/regular_metadata.json")?,
+    )?;
+    let reordered: serde_json::Map<_, _> =
+        ["ToolInfo", "SceneInfoSummary", "SceneInfo", "JSONInfo"]
+            .into_iter()
+            .map(|key| (key.to_string(), original[key].clone()))
+            .collect();
+
+    let input_json = temp.child("metadata.json");
+    input_json.write_str(&serde_json::to_string(&reordered)?)?;
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--json")
+        .arg(input_json.as_ref())
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_file
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_injected));
+
+    Ok(())
+}
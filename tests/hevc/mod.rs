@@ -1,3 +1,4 @@
+mod cut;
 mod extract;
 mod inject;
 mod remove;
@@ -72,3 +72,120 @@ fn sei_double_3byte_case() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn dry_run() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular.hevc");
+
+    let output_file = temp.child("hdr10plus_removed_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--dry-run")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Access units: 259"))
+        .stdout(predicate::str::contains("NALs dropped: 259"))
+        .stdout(predicate::str::contains("Input size: 32661 bytes"))
+        .stdout(predicate::str::contains(
+            "Projected output size: 18167 bytes",
+        ));
+
+    output_file.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn dry_run_stdin() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular.hevc");
+
+    let output_file = temp.child("hdr10plus_removed_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg("-")
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--dry-run")
+        .write_stdin(std::fs::read(input_file)?)
+        .assert();
+
+    // The input size is counted from the piped bytes
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Access units: 259"))
+        .stdout(predicate::str::contains("Input size: 32661 bytes"))
+        .stdout(predicate::str::contains(
+            "Projected output size: 18167 bytes",
+        ));
+
+    output_file.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn suffix_sei() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/suffix-sei.hevc");
+    let expected_removed = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+
+    let output_file = temp.child("hdr10plus_removed_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_file
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_removed));
+
+    Ok(())
+}
+
+#[test]
+fn duplicates() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/duplicate-sei.hevc");
+    let expected_removed = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+
+    let output_file = temp.child("hdr10plus_removed_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_file
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_removed));
+
+    Ok(())
+}
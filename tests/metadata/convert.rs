@@ -0,0 +1,166 @@
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::cargo;
+use assert_fs::prelude::*;
+use hdr10plus::metadata_binary::{MAGIC, MetadataBinaryReader};
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "convert";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "hdr10plus_tool convert [OPTIONS] [input_pos]",
+        ));
+    Ok(())
+}
+
+#[test]
+fn roundtrip() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let regular_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    // Same frames for the first two scenes, they can't be computed from the metadata
+    let mut same_scenes: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(regular_json)?)?;
+    let scene_info = same_scenes["SceneInfo"].as_array_mut().unwrap();
+
+    for i in 1..6 {
+        let mut frame = scene_info[0].clone();
+        for key in ["SceneFrameIndex", "SceneId", "SequenceFrameIndex"] {
+            frame[key] = scene_info[i][key].clone();
+        }
+
+        scene_info[i] = frame;
+    }
+
+    let same_scenes_json = temp.child("same_scenes.json");
+    same_scenes_json.write_str(&format!(
+        "{}\n",
+        serde_json::to_string_pretty(&same_scenes)?
+    ))?;
+
+    for input_json in [regular_json, same_scenes_json.path()] {
+        roundtrip_json(&temp, input_json)?;
+    }
+
+    Ok(())
+}
+
+/// Converts the JSON to binary, and back to the same JSON
+fn roundtrip_json(temp: &assert_fs::TempDir, input_json: &Path) -> Result<()> {
+    let output_bin = temp.child("metadata.bin");
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_bin.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let data = std::fs::read(output_bin.as_ref())?;
+    assert_eq!(&data[..MAGIC.len()], MAGIC);
+
+    let mut reader = MetadataBinaryReader::from_file(output_bin.as_ref())?;
+    assert_eq!(reader.header().frame_count, 259);
+    assert_eq!(reader.header().scene_count, 3);
+    assert_eq!(reader.by_ref().count(), 259);
+    assert_eq!(reader.scene_first_frames(), Some([0, 3, 6].as_slice()));
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(output_bin.as_ref())
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    // Including the scenes of the binary file
+    let input: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(input_json)?)?;
+    let output: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(output_json.path())?)?;
+    assert_eq!(
+        output["SceneInfoSummary"]["SceneFirstFrameIndex"],
+        serde_json::json!([0, 3, 6])
+    );
+    assert_eq!(input, output);
+
+    output_json
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(input_json));
+
+    Ok(())
+}
+
+#[test]
+fn inject_binary() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+    let input_bin = temp.child("metadata.bin");
+
+    let output_file = temp.child("injected_output.hevc");
+    let expected_injected = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+
+    cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--output")
+        .arg(input_bin.as_ref())
+        .assert()
+        .success();
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg("inject")
+        .arg(input_file)
+        .arg("--json")
+        .arg(input_bin.as_ref())
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--verify-output")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_file
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_injected));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_binary() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_bin = temp.child("metadata.bin");
+    input_bin.write_binary(b"HDR10+BM\x01\x00\x05\x00\x00\x00\x01\x00\x00\x00\x00\x00")?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_bin.as_ref())
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "Failed reading payload size of frame 0",
+    ));
+
+    Ok(())
+}
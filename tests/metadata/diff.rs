@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::cargo;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "diff";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "hdr10plus_tool diff [OPTIONS] <FIRST> <SECOND>",
+        ));
+    Ok(())
+}
+
+#[test]
+fn hevc_same_as_json() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+    let input_file = Path::new("assets/hevc_tests/regular.hevc");
+
+    let assert = cmd.arg(SUBCOMMAND).arg(input_json).arg(input_file).assert();
+
+    assert
+        .success()
+        .stdout(predicate::str::contains(
+            "Frames: 259 in first, 259 in second",
+        ))
+        .stdout(predicate::str::contains("No differences found."));
+
+    Ok(())
+}
+
+#[test]
+fn deleted_scene() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("remove.json");
+    let cfg_file = File::create(&edit_config)?;
+    serde_json::to_writer(cfg_file, &serde_json::json!({ "remove": ["3-5"] }))?;
+
+    let edited_json = temp.child("metadata.json");
+
+    cargo::cargo_bin_cmd!()
+        .arg("editor")
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(edited_json.as_ref())
+        .assert()
+        .success();
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg(edited_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Frames: 259 in first, 256 in second",
+        ))
+        .stdout(predicate::str::contains(
+            "Deleted from first: scene 1, frames 3-5 (3 frames)",
+        ))
+        .stdout(predicate::str::contains("3 frames differ, in 1 ranges:"))
+        .stdout(predicate::str::contains("Frames 3-5:"))
+        .stdout(predicate::str::contains("average_maxrgb: 297 -> 911"));
+
+    // Aligned on the remaining frames
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(edited_json.as_ref())
+        .arg(input_json)
+        .arg("--offset")
+        .arg("3")
+        .assert();
+
+    assert
+        .success()
+        .stdout(predicate::str::contains("Frames 0-2 (second: 3-5):"))
+        .stdout(predicate::str::contains("ranges").count(1));
+
+    Ok(())
+}
+
+#[test]
+fn tolerance() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let mut metadata: serde_json::Value = serde_json::from_reader(File::open(input_json)?)?;
+    metadata["SceneInfo"][10]["LuminanceParameters"]["MaxScl"][0] = 17518.into();
+
+    let edited_json = temp.child("metadata.json");
+    serde_json::to_writer(File::create(&edited_json)?, &metadata)?;
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg(edited_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stdout(predicate::str::contains("Frame 10:"))
+        .stdout(predicate::str::contains(
+            "maxscl: [17513, 16895, 14316] -> [17518, 16895, 14316]",
+        ));
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg(edited_json.as_ref())
+        .arg("--tolerance")
+        .arg("0.5")
+        .assert();
+
+    assert
+        .success()
+        .stdout(predicate::str::contains("No differences found."));
+
+    Ok(())
+}
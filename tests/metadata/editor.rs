@@ -3,6 +3,7 @@ use std::{fs::File, path::Path};
 use anyhow::Result;
 use assert_cmd::cargo;
 use assert_fs::prelude::*;
+use hdr10plus::metadata_binary::MetadataBinaryReader;
 use hdr10plus::metadata_json::MetadataJsonRoot;
 use predicates::prelude::*;
 
@@ -115,3 +116,850 @@ fn remove() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn remove_invalid_range() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("remove.json");
+    let cfg_file = std::fs::File::create(&edit_config)?;
+    serde_json::to_writer(
+        cfg_file,
+        &serde_json::json!({
+            "remove": [
+                "5-2"
+            ]
+        }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "invalid range 5-2, start is after end",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn binary() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+    let input_bin = temp.child("metadata.bin");
+
+    cargo::cargo_bin_cmd!()
+        .arg("convert")
+        .arg(input_json)
+        .arg("--output")
+        .arg(input_bin.as_ref())
+        .assert()
+        .success();
+
+    let edit_config = temp.child("remove.json");
+    let cfg_file = std::fs::File::create(&edit_config)?;
+    serde_json::to_writer(
+        cfg_file,
+        &serde_json::json!({
+            "remove": [
+                "0-2"
+            ]
+        }),
+    )?;
+
+    let output_bin = temp.child("metadata_modified.bin");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_bin.as_ref())
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_bin.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let mut reader = MetadataBinaryReader::from_file(output_bin.as_ref())?;
+    assert_eq!(reader.by_ref().count(), 256);
+    assert_eq!(reader.scene_first_frames(), Some([0, 3].as_slice()));
+
+    Ok(())
+}
+
+#[test]
+fn brightness() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("brightness.json");
+    let cfg_file = std::fs::File::create(&edit_config)?;
+    serde_json::to_writer(
+        cfg_file,
+        &serde_json::json!({
+            "scale": [
+                { "range": "0-2", "factor": 0.5 }
+            ],
+            "pq_offset": [
+                { "range": "5", "offset": 0.1 }
+            ],
+            "clamp": [
+                { "max_nits": 100.0 }
+            ]
+        }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Scaled brightness of 3 frames by 0.5.",
+        ))
+        .stdout(predicate::str::contains(
+            "Clamped brightness of 259 frames to 100 nits.",
+        ));
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(output_json.as_ref())?)?;
+    let scene_info = metadata_json.scene_info.as_slice();
+
+    // Scaled, then clamped
+    let params = &scene_info[0].luminance_parameters;
+    assert_eq!(params.average_rgb, 519);
+    assert_eq!(params.max_scl, vec![1000, 1000, 1000]);
+    assert_eq!(
+        params.luminance_distributions.distribution_values,
+        vec![2, 14024, 22, 28, 110, 518, 1000, 1000, 1000]
+    );
+
+    // Only clamped
+    assert_eq!(scene_info[3].luminance_parameters.average_rgb, 297);
+
+    // Offset in PQ, then clamped
+    assert_eq!(scene_info[5].luminance_parameters.average_rgb, 851);
+
+    Ok(())
+}
+
+#[test]
+fn brightness_limits() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("scale.json");
+    let cfg_file = std::fs::File::create(&edit_config)?;
+    serde_json::to_writer(
+        cfg_file,
+        &serde_json::json!({
+            "scale": [
+                { "factor": 100.0 }
+            ]
+        }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(output_json.as_ref())?)?;
+
+    for metadata in &metadata_json.scene_info {
+        let params = &metadata.luminance_parameters;
+
+        assert!(params.max_scl.iter().all(|v| *v <= 100_000));
+        assert!(params.average_rgb <= 100_000);
+        assert!(
+            params
+                .luminance_distributions
+                .distribution_values
+                .iter()
+                .all(|v| *v <= 100_000)
+        );
+    }
+
+    assert_eq!(
+        metadata_json.scene_info[0].luminance_parameters.max_scl,
+        vec![100_000, 100_000, 100_000]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn profile_conversion() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("profile_b.json");
+    let cfg_file = std::fs::File::create(&edit_config)?;
+    serde_json::to_writer(
+        cfg_file,
+        &serde_json::json!({
+            "profile_b": [
+                {
+                    "scenes": "1",
+                    "target_luminance": 400,
+                    "knee_point_x": 17,
+                    "knee_point_y": 64,
+                    "anchors": [102, 205, 307, 410, 512, 614, 717, 819, 922]
+                }
+            ],
+            "target_luminance": [
+                { "range": "4-5", "nits": 1000 }
+            ]
+        }),
+    )?;
+
+    let mixed_json = temp.child("mixed.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(mixed_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Converted 3 frames to profile B."));
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(mixed_json.as_ref())?)?;
+    let scene_info = metadata_json.scene_info.as_slice();
+
+    assert_eq!(metadata_json.info.profile, "N/A");
+    assert!(scene_info[2].bezier_curve_data.is_none());
+    assert_eq!(scene_info[2].targeted_system_display_maximum_luminance, 0);
+
+    let bezier_curve = scene_info[3].bezier_curve_data.as_ref().unwrap();
+    assert_eq!(bezier_curve.knee_point_x, 17);
+    assert_eq!(bezier_curve.knee_point_y, 64);
+    assert_eq!(scene_info[3].targeted_system_display_maximum_luminance, 400);
+    assert_eq!(
+        scene_info[4].targeted_system_display_maximum_luminance,
+        1000
+    );
+    assert!(scene_info[6].bezier_curve_data.is_none());
+
+    // Back to the original
+    let edit_config = temp.child("profile_a.json");
+    let cfg_file = std::fs::File::create(&edit_config)?;
+    serde_json::to_writer(cfg_file, &serde_json::json!({ "profile_a": [{}] }))?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(mixed_json.as_ref())
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+    output_json.assert(predicate::path::eq_file(input_json));
+
+    Ok(())
+}
+
+#[test]
+fn target_luminance_profile_a() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("target.json");
+    let cfg_file = std::fs::File::create(&edit_config)?;
+    serde_json::to_writer(
+        cfg_file,
+        &serde_json::json!({
+            "target_luminance": [
+                { "scenes": "1-2", "nits": 1000 }
+            ]
+        }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "frame 3 is profile A, it must be converted with profile_b to set its target luminance",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn scene_edits() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("scenes.json");
+    let cfg_file = std::fs::File::create(&edit_config)?;
+    serde_json::to_writer(
+        cfg_file,
+        &serde_json::json!({
+            "replace_scene": [
+                { "scenes": "2", "source": 1 }
+            ],
+            "scale": [
+                { "scenes": "0,1", "factor": 2.0 }
+            ]
+        }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Replaced the metadata of 1 scenes with scene 1.",
+        ))
+        .stdout(predicate::str::contains(
+            "Scaled brightness of 6 frames by 2.",
+        ));
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(output_json.as_ref())?)?;
+    let scene_info = metadata_json.scene_info.as_slice();
+
+    assert_eq!(scene_info.len(), 259);
+    assert_eq!(scene_info[0].luminance_parameters.average_rgb, 2074);
+    assert_eq!(scene_info[3].luminance_parameters.average_rgb, 594);
+    // Past the end of the source scene
+    assert_eq!(scene_info[258].luminance_parameters.average_rgb, 297);
+    assert_eq!(
+        scene_info[258].luminance_parameters.max_scl,
+        [20487, 20579, 17047]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn merge_scenes() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("merge.json");
+    let cfg_file = std::fs::File::create(&edit_config)?;
+    serde_json::to_writer(
+        cfg_file,
+        &serde_json::json!({
+            "merge_scenes": [
+                { "scenes": "0-1", "mode": "average" }
+            ],
+            "remove_scenes": ["2"]
+        }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Merged 2 scenes."))
+        .stdout(predicate::str::contains(
+            "Removed 253 metadata frames of scenes.",
+        ));
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(output_json.as_ref())?)?;
+
+    assert_eq!(metadata_json.scene_info.len(), 6);
+    assert_eq!(
+        metadata_json.scene_info_summary.scene_first_frame_index,
+        [0]
+    );
+    assert!(
+        metadata_json
+            .scene_info
+            .iter()
+            .all(|frame| frame.luminance_parameters.average_rgb == 667
+                && frame.luminance_parameters.max_scl == [19159, 18737, 15650])
+    );
+
+    // Scenes that aren't adjacent
+    let edit_config = temp.child("merge_invalid.json");
+    let cfg_file = std::fs::File::create(&edit_config)?;
+    serde_json::to_writer(
+        cfg_file,
+        &serde_json::json!({ "merge_scenes": [{ "scenes": "0,2" }] }),
+    )?;
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "invalid scenes to merge 0,2, they must be adjacent",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn timecodes() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let frames_config = temp.child("frames.json");
+    serde_json::to_writer(
+        File::create(&frames_config)?,
+        &serde_json::json!({
+            "remove": ["24-35"],
+            "scale": [{ "range": "0-2,12", "factor": 0.5 }],
+            "duplicate": [{ "source": 48, "offset": 60, "length": 2 }]
+        }),
+    )?;
+
+    let timecodes_config = temp.child("timecodes.json");
+    serde_json::to_writer(
+        File::create(&timecodes_config)?,
+        &serde_json::json!({
+            "remove": ["00:00:01:00-1490ms"],
+            "scale": [{ "range": "00:00:00:00-00:00:00:02,500ms", "factor": 0.5 }],
+            "duplicate": [{ "source": "00:00:02:00", "offset": "2500ms", "length": 2 }]
+        }),
+    )?;
+
+    let frames_json = temp.child("frames_metadata.json");
+    let timecodes_json = temp.child("timecodes_metadata.json");
+
+    cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(frames_config.as_ref())
+        .arg("--json-out")
+        .arg(frames_json.as_ref())
+        .assert()
+        .success();
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(timecodes_config.as_ref())
+        .arg("--json-out")
+        .arg(timecodes_json.as_ref())
+        .arg("--fps")
+        .arg("24")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Removed 12 metadata frames."));
+    timecodes_json.assert(predicate::path::eq_file(frames_json.path()));
+
+    // Same frame rate, from the VUI of a 24 fps video
+    let vui_json = temp.child("vui_metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(timecodes_config.as_ref())
+        .arg("--json-out")
+        .arg(vui_json.as_ref())
+        .arg("--input-hevc")
+        .arg("assets/hevc_tests/dhdr10-opt.hevc")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Frame rate from the HEVC file: 24.000",
+        ));
+    vui_json.assert(predicate::path::eq_file(frames_json.path()));
+
+    // The frame rate is required
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(timecodes_config.as_ref())
+        .arg("--json-out")
+        .arg(timecodes_json.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "2500ms to a frame requires the frame rate, set with --fps or --input-hevc",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn drop_frame_timecode() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("timecodes.json");
+    serde_json::to_writer(
+        File::create(&edit_config)?,
+        &serde_json::json!({ "remove": ["00:00:59;29-00:01:00;00"] }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .arg("--fps")
+        .arg("30000/1001")
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "Invalid drop-frame timecode 00:01:00;00, the frame number is skipped",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn frame_rate_pulldown() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("pulldown.json");
+    serde_json::to_writer(
+        File::create(&edit_config)?,
+        &serde_json::json!({
+            "frame_rate": { "source": "24000/1001", "target": "30000/1001", "mode": "pulldown" }
+        }),
+    )?;
+
+    let telecined_json = temp.child("telecined.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(telecined_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Converted 259 frames at 23.976 fps to 323 frames at 29.970 fps.",
+        ));
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(telecined_json.as_ref())?)?;
+    let summary = metadata_json.scene_info_summary;
+
+    // The cuts are on the first frames with a field of the new scene
+    assert_eq!(summary.scene_first_frame_index, [0, 3, 7]);
+    assert_eq!(summary.scene_frame_numbers, [3, 4, 316]);
+
+    // Inverse telecine back to the original
+    let edit_config = temp.child("inverse.json");
+    serde_json::to_writer(
+        File::create(&edit_config)?,
+        &serde_json::json!({
+            "frame_rate": { "target": 23.976, "mode": "pulldown" }
+        }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(telecined_json.as_ref())
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .arg("--fps")
+        .arg("29.97")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+    output_json.assert(predicate::path::eq_file(input_json));
+
+    Ok(())
+}
+
+#[test]
+fn frame_rate_resample() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("resample.json");
+    serde_json::to_writer(
+        File::create(&edit_config)?,
+        &serde_json::json!({ "frame_rate": { "target": "60000/1001" } }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .arg("--fps")
+        .arg("24000/1001")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Converted 259 frames at 23.976 fps to 647 frames at 59.940 fps.",
+        ));
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(output_json.as_ref())?)?;
+    assert_eq!(
+        metadata_json.scene_info_summary.scene_first_frame_index,
+        [0, 8, 15]
+    );
+
+    // Scenes too short for the target frame rate
+    let edit_config = temp.child("drop.json");
+    serde_json::to_writer(
+        File::create(&edit_config)?,
+        &serde_json::json!({
+            "remove": ["1-2", "4-5"],
+            "frame_rate": { "source": 24, "target": 10 }
+        }),
+    )?;
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stdout(predicate::str::contains(
+            "Warning: 1 scenes shorter than a frame at 10.000 fps were dropped",
+        ))
+        .stdout(predicate::str::contains(
+            "Converted 255 frames at 24.000 fps to 106 frames at 10.000 fps.",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn smooth() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    // A single frame spike inside a scene
+    let mut spike: serde_json::Value = serde_json::from_reader(File::open(input_json)?)?;
+    spike["SceneInfo"][100]["LuminanceParameters"]["AverageRGB"] = 5000.into();
+
+    let spike_json = temp.child("spike.json");
+    serde_json::to_writer_pretty(File::create(&spike_json)?, &spike)?;
+
+    let edit_config = temp.child("median.json");
+    serde_json::to_writer(
+        File::create(&edit_config)?,
+        &serde_json::json!({ "smooth": [{ "method": "median", "window": 3 }] }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(spike_json.as_ref())
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Smoothed 259 frames, reset at 2 cuts.",
+        ));
+    output_json.assert(predicate::path::eq_file(input_json));
+
+    // The real cuts are kept sharp without the scene information
+    let edit_config = temp.child("exponential.json");
+    serde_json::to_writer(
+        File::create(&edit_config)?,
+        &serde_json::json!({
+            "smooth": [{ "method": "exponential", "scene_cuts": false, "cut_threshold": 0.05 }]
+        }),
+    )?;
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+    output_json.assert(predicate::path::eq_file(input_json));
+
+    // Smoothed across the cuts
+    let edit_config = temp.child("average.json");
+    serde_json::to_writer(
+        File::create(&edit_config)?,
+        &serde_json::json!({ "smooth": [{ "scene_cuts": false }] }),
+    )?;
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stdout(predicate::str::contains(
+        "Smoothed 259 frames, reset at 0 cuts.",
+    ));
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(output_json.as_ref())?)?;
+    let average_rgb: Vec<u32> = metadata_json.scene_info[..9]
+        .iter()
+        .map(|frame| frame.luminance_parameters.average_rgb)
+        .collect();
+
+    assert_eq!(average_rgb, [1037, 768, 639, 498, 484, 471, 589, 734, 911]);
+    assert_eq!(
+        metadata_json.scene_info_summary.scene_first_frame_index,
+        [0, 1, 2, 3, 4, 5, 6, 7, 8]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn smooth_invalid_window() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let edit_config = temp.child("smooth.json");
+    serde_json::to_writer(
+        File::create(&edit_config)?,
+        &serde_json::json!({ "smooth": [{ "window": 4 }] }),
+    )?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--json-out")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "invalid smoothing window 4, it must be odd",
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::cargo;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "info";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "hdr10plus_tool info [OPTIONS] [input_pos]",
+        ));
+    Ok(())
+}
+
+#[test]
+fn summary() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cmd.arg(SUBCOMMAND).arg(input_json).assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Profile: A"))
+        .stdout(predicate::str::contains("Frames: 259"))
+        .stdout(predicate::str::contains("Scenes: 3"))
+        .stdout(predicate::str::contains("Max length: 253 frames"))
+        .stdout(predicate::str::contains("MaxFALL: 103.70 nits"))
+        .stdout(predicate::str::contains("max-scl: 2057.90 nits"))
+        .stdout(predicate::str::contains(
+            "Distribution percentiles: 9 entries",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn json_summary() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let output = cmd.arg(SUBCOMMAND).arg(input_json).arg("--json").output()?;
+    assert!(output.status.success());
+
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+
+    assert_eq!(summary["profile"], "A");
+    assert_eq!(summary["application_versions"], serde_json::json!([1]));
+    assert_eq!(summary["frame_count"], 259);
+    assert_eq!(summary["scene_count"], 3);
+    assert_eq!(summary["max_scene_length"], 253);
+    assert_eq!(summary["max_cll"]["histogram"], 1444.5);
+    assert_eq!(
+        summary["targeted_system_display_maximum_luminance"],
+        serde_json::json!([0])
+    );
+    assert!(summary["knee_point"].is_null());
+    assert_eq!(
+        summary["distribution_maxrgb_percentiles"],
+        serde_json::json!([9])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn hevc_same_as_json() -> Result<()> {
+    let input_file = Path::new("assets/hevc_tests/regular.hevc");
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let expected = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--json")
+        .output()?;
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--json")
+        .assert();
+
+    assert
+        .success()
+        .stdout(predicate::eq(expected.stdout.as_slice()));
+
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::cargo;
+use assert_fs::prelude::*;
+use hdr10plus::metadata_json::MetadataJsonRoot;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "merge";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "hdr10plus_tool merge [OPTIONS] --output <OUTPUT> <INPUTS>...",
+        ));
+    Ok(())
+}
+
+#[test]
+fn ranges() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+    let output_json = temp.child("metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(format!("{}:0-99", input_json.display()))
+        .arg(format!("{}:100-258", input_json.display()))
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_json
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(input_json));
+
+    Ok(())
+}
+
+#[test]
+fn concat() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+    let output_json = temp.child("metadata.json");
+
+    let assert = cmd
+        .arg("concat")
+        .arg(input_json)
+        .arg(format!("{}:3-5", input_json.display()))
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(output_json.as_ref())?)?;
+    let scene_info = metadata_json.scene_info.as_slice();
+
+    assert_eq!(scene_info.len(), 262);
+    assert_eq!(scene_info[261].sequence_frame_index, 261);
+    assert_eq!(scene_info[259].scene_id, 3);
+    assert_eq!(scene_info[259].scene_frame_index, 0);
+    assert_eq!(
+        metadata_json.scene_info_summary.scene_first_frame_index,
+        vec![0, 3, 6, 259]
+    );
+    assert_eq!(
+        metadata_json.scene_info_summary.scene_frame_numbers,
+        vec![3, 3, 253, 3]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn invalid_range() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+    let output_json = temp.child("metadata.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(format!("{}:100-259", input_json.display()))
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "Invalid range 100-259 for assets/hevc_tests/regular_metadata.json, it has 259 frames",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn mixed_profiles() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    // Profile B version of the same metadata
+    let mut metadata: serde_json::Value = serde_json::from_reader(File::open(input_json)?)?;
+    metadata["JSONInfo"]["HDR10plusProfile"] = "B".into();
+
+    for frame in metadata["SceneInfo"].as_array_mut().unwrap() {
+        frame["TargetedSystemDisplayMaximumLuminance"] = 400.into();
+        frame["BezierCurveData"] = serde_json::json!({
+            "Anchors": [102, 205, 307, 410, 512, 614, 717, 819, 922],
+            "KneePointX": 0,
+            "KneePointY": 0
+        });
+    }
+
+    let profile_b_json = temp.child("profile_b.json");
+    serde_json::to_writer(File::create(&profile_b_json)?, &metadata)?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg(profile_b_json.as_ref())
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "Inputs have both profile A and profile B metadata, use --mixed-profiles to merge them",
+    ));
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg(profile_b_json.as_ref())
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--mixed-profiles")
+        .arg("profile-a")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(output_json.as_ref())?)?;
+
+    assert_eq!(metadata_json.info.profile, "A");
+    assert_eq!(metadata_json.scene_info.len(), 518);
+    assert!(
+        metadata_json
+            .scene_info
+            .iter()
+            .all(|frame| frame.bezier_curve_data.is_none()
+                && frame.targeted_system_display_maximum_luminance == 0)
+    );
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg(profile_b_json.as_ref())
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--mixed-profiles")
+        .arg("keep")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let metadata_json: MetadataJsonRoot =
+        serde_json::from_reader(File::open(output_json.as_ref())?)?;
+
+    assert_eq!(metadata_json.info.profile, "N/A");
+    assert!(metadata_json.scene_info[259].bezier_curve_data.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn internally_mixed_profiles() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    // Profile B for the first 100 frames only, the file's profile is N/A
+    let mut metadata: serde_json::Value = serde_json::from_reader(File::open(input_json)?)?;
+    metadata["JSONInfo"]["HDR10plusProfile"] = "N/A".into();
+
+    for frame in metadata["SceneInfo"]
+        .as_array_mut()
+        .unwrap()
+        .iter_mut()
+        .take(100)
+    {
+        frame["TargetedSystemDisplayMaximumLuminance"] = 400.into();
+        frame["BezierCurveData"] = serde_json::json!({
+            "Anchors": [102, 205, 307, 410, 512, 614, 717, 819, 922],
+            "KneePointX": 0,
+            "KneePointY": 0
+        });
+    }
+
+    let mixed_json = temp.child("mixed.json");
+    serde_json::to_writer(File::create(&mixed_json)?, &metadata)?;
+
+    let output_json = temp.child("metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg(mixed_json.as_ref())
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "Inputs have both profile A and profile B metadata, use --mixed-profiles to merge them",
+    ));
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(mixed_json.as_ref())
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--mixed-profiles")
+        .arg("profile-a")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+    output_json.assert(predicate::path::eq_file(input_json));
+
+    Ok(())
+}
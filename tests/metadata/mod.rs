@@ -1,2 +1,7 @@
+mod convert;
+mod diff;
 mod editor;
+mod info;
+mod merge;
 mod plot;
+mod split;
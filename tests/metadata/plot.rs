@@ -42,3 +42,91 @@ fn plot_hdr10plus() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn invalid_range() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+    let output_file = temp.child("plot.png");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--end")
+        .arg("259")
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "Invalid frame range: end 259 is out of bounds, last frame is 258",
+    ));
+
+    output_file.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn json_keys_order() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // Summary and JSONInfo after the frames
+    let original: serde_json::Map<String, serde_json::Value> = serde_json::from_str(
+        &std::fs::read_to_string("assets/hevc_tests/regular_metadata.json")?,
+    )?;
+    let reordered: serde_json::Map<_, _> = ["SceneInfo", "SceneInfoSummary", "JSONInfo"]
+        .into_iter()
+        .map(|key| (key.to_string(), original[key].clone()))
+        .collect();
+
+    let input_json = temp.child("metadata.json");
+    input_json.write_str(&serde_json::to_string_pretty(&reordered)?)?;
+
+    let output_file = temp.child("plot.png");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_json.as_ref())
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+    output_file.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn plot_binary() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+    let input_bin = temp.child("metadata.bin");
+    let output_file = temp.child("plot.png");
+
+    cargo::cargo_bin_cmd!()
+        .arg("convert")
+        .arg(input_json)
+        .arg("--output")
+        .arg(input_bin.as_ref())
+        .assert()
+        .success();
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_bin.as_ref())
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_file.assert(predicate::path::is_file());
+
+    Ok(())
+}
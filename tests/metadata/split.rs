@@ -0,0 +1,157 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::cargo;
+use assert_fs::prelude::*;
+use hdr10plus::metadata_json::MetadataJsonRoot;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "split";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = cargo::cargo_bin_cmd!();
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "hdr10plus_tool split [OPTIONS] <--ranges <RANGES>|--scenes <N>|--chapters <CHAPTERS>> [input_pos]",
+        ));
+    Ok(())
+}
+
+#[test]
+fn ranges() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--ranges")
+        .arg("0-4,5-258")
+        .arg("--output-dir")
+        .arg(temp.path())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let first = temp.child("regular_metadata_part1.json");
+    let second = temp.child("regular_metadata_part2.json");
+
+    let metadata_json: MetadataJsonRoot = serde_json::from_reader(File::open(second.as_ref())?)?;
+    let scene_info = metadata_json.scene_info.as_slice();
+
+    // Renumbered from zero
+    assert_eq!(scene_info.len(), 254);
+    assert_eq!(scene_info[0].sequence_frame_index, 0);
+    assert_eq!(scene_info[0].scene_frame_index, 0);
+    assert_eq!(scene_info[1].scene_id, 1);
+    assert_eq!(scene_info[1].scene_frame_index, 0);
+    assert_eq!(
+        metadata_json.scene_info_summary.scene_frame_numbers,
+        vec![1, 253]
+    );
+
+    // Merged back to the original
+    let merged_json = temp.child("merged.json");
+
+    cargo::cargo_bin_cmd!()
+        .arg("merge")
+        .arg(first.as_ref())
+        .arg(second.as_ref())
+        .arg("--output")
+        .arg(merged_json.as_ref())
+        .assert()
+        .success();
+
+    merged_json.assert(predicate::path::eq_file(input_json));
+
+    Ok(())
+}
+
+#[test]
+fn scenes() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--scenes")
+        .arg("2")
+        .arg("--output-dir")
+        .arg(temp.path())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("frames 0-5 (6 frames)"))
+        .stdout(predicate::str::contains("frames 6-258 (253 frames)"));
+
+    temp.child("regular_metadata_part2.json")
+        .assert(predicate::path::is_file());
+    temp.child("regular_metadata_part3.json")
+        .assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn chapters() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let chapters = temp.child("chapters.txt");
+    chapters.write_str(
+        "CHAPTER01=00:00:00.000\nCHAPTER01NAME=Intro\nCHAPTER02=00:00:04.171\nCHAPTER02NAME=Second\n",
+    )?;
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--chapters")
+        .arg(chapters.as_ref())
+        .arg("--fps")
+        .arg("24000/1001")
+        .arg("--output-dir")
+        .arg(temp.path())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("frames 0-99 (100 frames)"))
+        .stdout(predicate::str::contains("frames 100-258 (159 frames)"));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_range() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_json = Path::new("assets/hevc_tests/regular_metadata.json");
+
+    let assert = cargo::cargo_bin_cmd!()
+        .arg(SUBCOMMAND)
+        .arg(input_json)
+        .arg("--ranges")
+        .arg("0-259")
+        .arg("--output-dir")
+        .arg(temp.path())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "Invalid range 0-259, the metadata has 259 frames",
+    ));
+
+    Ok(())
+}